@@ -0,0 +1,245 @@
+use crate::{Debuggable, FormatError, FormatSpec, IndentWriter, Writeable};
+
+/// Writes a value with the given writer and spec, returning the written char count.
+pub type WriteFn<'a> = &'a dyn Fn(&mut dyn Writeable, &FormatSpec) -> Result<usize, FormatError>;
+
+// State shared by the builders: the written char count, or the first writer error
+struct DebugInner<'a> {
+    writer: &'a mut dyn Writeable,
+    spec: FormatSpec,
+    result: Result<usize, FormatError>,
+    has_entries: bool,
+}
+
+impl<'a> DebugInner<'a> {
+    fn new(writer: &'a mut dyn Writeable, spec: &FormatSpec, name: &str) -> DebugInner<'a> {
+        // An invalid radix fails before the name or the brackets are written, like it does for a
+        // single integer
+        let result = match spec.radix {
+            Some(radix) if !(2..=36).contains(&radix) => Err(FormatError::InvalidSpec),
+            _ => Ok(0),
+        };
+        let mut inner = DebugInner {
+            writer,
+            spec: *spec,
+            result,
+            has_entries: false,
+        };
+        inner.write_str(name);
+        inner
+    }
+
+    fn add(&mut self, result: Result<usize, FormatError>) {
+        self.result = match (self.result, result) {
+            (Ok(count), Ok(written)) => Ok(count + written),
+            (Ok(_), Err(e)) | (Err(e), _) => Err(e),
+        };
+    }
+
+    fn write_str(&mut self, text: &str) {
+        if self.result.is_ok() {
+            let result = self.writer.write_str(text);
+            self.add(result.map(|_| text.chars().count()));
+        }
+    }
+
+    // `open` is written before the first entry, `", "` between the others. With the pretty hint,
+    // every entry is written on its own indented line, followed by a comma.
+    fn entry(&mut self, open: &str, write: WriteFn) {
+        if self.result.is_err() {
+            return;
+        }
+        if self.spec.alternate {
+            if !self.has_entries {
+                self.write_str(open.trim_end());
+                self.write_str("\n");
+                if self.result.is_err() {
+                    self.has_entries = true;
+                    return;
+                }
+            }
+            let mut indent = IndentWriter::new(&mut *self.writer);
+            let result = write(&mut indent, &self.spec).and_then(|_| indent.write_str(",\n"));
+            let written = indent.written;
+            self.add(result.map(|_| written));
+        } else {
+            self.write_str(if self.has_entries { ", " } else { open });
+            if self.result.is_ok() {
+                let result = write(&mut *self.writer, &self.spec);
+                self.add(result);
+            }
+        }
+        self.has_entries = true;
+    }
+
+    // `empty` is written when there was no entry
+    fn finish(&mut self, close: &str, empty: &str) -> Result<usize, FormatError> {
+        if !self.has_entries {
+            self.write_str(empty);
+        } else if self.spec.alternate {
+            self.write_str(close.trim_start());
+        } else {
+            self.write_str(close);
+        }
+        self.result
+    }
+}
+
+/// Writes `Name { a: 1, b: 2 }`, like `core::fmt::Formatter::debug_struct`.
+pub struct DebugStruct<'a> {
+    inner: DebugInner<'a>,
+}
+
+pub fn debug_struct<'a>(
+    writer: &'a mut dyn Writeable,
+    spec: &FormatSpec,
+    name: &str,
+) -> DebugStruct<'a> {
+    DebugStruct {
+        inner: DebugInner::new(writer, spec, name),
+    }
+}
+
+impl DebugStruct<'_> {
+    pub fn field(&mut self, name: &str, value: &dyn Debuggable) -> &mut Self {
+        self.field_with(name, &|writer, spec| {
+            value.write_debug_aligned(writer, spec)
+        })
+    }
+
+    /// Same as `field`, with a custom function writing the value.
+    pub fn field_with(&mut self, name: &str, write: WriteFn) -> &mut Self {
+        self.inner.entry(" { ", &|writer, spec| {
+            writer.write_str(name)?;
+            writer.write_str(": ")?;
+            Ok(name.chars().count() + 2 + write(writer, spec)?)
+        });
+        self
+    }
+
+    /// Returns the written char count, or the first writer error.
+    pub fn finish(&mut self) -> Result<usize, FormatError> {
+        self.inner.finish(" }", "")
+    }
+}
+
+/// Writes `Name(1, 2)`, like `core::fmt::Formatter::debug_tuple`.
+pub struct DebugTuple<'a> {
+    inner: DebugInner<'a>,
+    fields: usize,
+    unnamed: bool,
+}
+
+pub fn debug_tuple<'a>(
+    writer: &'a mut dyn Writeable,
+    spec: &FormatSpec,
+    name: &str,
+) -> DebugTuple<'a> {
+    DebugTuple {
+        inner: DebugInner::new(writer, spec, name),
+        fields: 0,
+        unnamed: name.is_empty(),
+    }
+}
+
+impl DebugTuple<'_> {
+    pub fn field(&mut self, value: &dyn Debuggable) -> &mut Self {
+        self.field_with(&|writer, spec| value.write_debug_aligned(writer, spec))
+    }
+
+    /// Same as `field`, with a custom function writing the value.
+    pub fn field_with(&mut self, write: WriteFn) -> &mut Self {
+        self.inner.entry("(", write);
+        self.fields += 1;
+        self
+    }
+
+    /// Returns the written char count, or the first writer error.
+    pub fn finish(&mut self) -> Result<usize, FormatError> {
+        // `(1,)` is a tuple, `(1)` would not be
+        if self.unnamed && self.fields == 1 && !self.inner.spec.alternate {
+            self.inner.write_str(",");
+        }
+        self.inner.finish(")", "")
+    }
+}
+
+/// Writes `[1, 2]`, like `core::fmt::Formatter::debug_list`.
+pub struct DebugList<'a> {
+    inner: DebugInner<'a>,
+}
+
+pub fn debug_list<'a>(writer: &'a mut dyn Writeable, spec: &FormatSpec) -> DebugList<'a> {
+    DebugList {
+        inner: DebugInner::new(writer, spec, ""),
+    }
+}
+
+impl DebugList<'_> {
+    pub fn entry(&mut self, value: &dyn Debuggable) -> &mut Self {
+        self.entry_with(&|writer, spec| value.write_debug_aligned(writer, spec))
+    }
+
+    /// Same as `entry`, with a custom function writing the value.
+    pub fn entry_with(&mut self, write: WriteFn) -> &mut Self {
+        self.inner.entry("[", write);
+        self
+    }
+
+    pub fn entries<D: Debuggable, I: IntoIterator<Item = D>>(&mut self, entries: I) -> &mut Self {
+        for entry in entries {
+            self.entry(&entry);
+        }
+        self
+    }
+
+    /// Returns the written char count, or the first writer error.
+    pub fn finish(&mut self) -> Result<usize, FormatError> {
+        self.inner.finish("]", "[]")
+    }
+}
+
+/// Writes `{a: 1, b: 2}`, like `core::fmt::Formatter::debug_map`.
+pub struct DebugMap<'a> {
+    inner: DebugInner<'a>,
+}
+
+pub fn debug_map<'a>(writer: &'a mut dyn Writeable, spec: &FormatSpec) -> DebugMap<'a> {
+    DebugMap {
+        inner: DebugInner::new(writer, spec, ""),
+    }
+}
+
+impl DebugMap<'_> {
+    pub fn entry(&mut self, key: &dyn Debuggable, value: &dyn Debuggable) -> &mut Self {
+        self.entry_with(
+            &|writer, spec| key.write_debug_aligned(writer, spec),
+            &|writer, spec| value.write_debug_aligned(writer, spec),
+        )
+    }
+
+    /// Same as `entry`, with custom functions writing the key and the value.
+    pub fn entry_with(&mut self, key: WriteFn, value: WriteFn) -> &mut Self {
+        self.inner.entry("{", &|writer, spec| {
+            let count = key(writer, spec)?;
+            writer.write_str(": ")?;
+            Ok(count + 2 + value(writer, spec)?)
+        });
+        self
+    }
+
+    pub fn entries<K: Debuggable, V: Debuggable, I: IntoIterator<Item = (K, V)>>(
+        &mut self,
+        entries: I,
+    ) -> &mut Self {
+        for (key, value) in entries {
+            self.entry(&key, &value);
+        }
+        self
+    }
+
+    /// Returns the written char count, or the first writer error.
+    pub fn finish(&mut self) -> Result<usize, FormatError> {
+        self.inner.finish("}", "{}")
+    }
+}
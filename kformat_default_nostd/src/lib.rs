@@ -1,407 +1,567 @@
 #![no_std]
 
+use core::{
+    cmp::Ordering,
+    fmt::Alignment,
+    num::{
+        NonZeroI128, NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroIsize, NonZeroU128,
+        NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU8, NonZeroUsize, Wrapping,
+    },
+    time::Duration,
+};
+
+mod builders;
+
+pub use builders::{
+    debug_list, debug_map, debug_struct, debug_tuple, DebugList, DebugMap, DebugStruct, DebugTuple,
+    WriteFn,
+};
+
+// The derives refer to this crate as `::kformat_default_nostd`
+extern crate self as kformat_default_nostd;
+
+#[doc(hidden)]
+pub use kformat_macros::kwrite_facade;
+pub use kformat_macros::{define_kformat, kwrite_to_raw, Debuggable, Formattable};
+
+/// Destination of `kwrite!`.
+///
+/// A writer with a fixed capacity, like a buffer, returns `FormatError::Overflow` when it has no
+/// room left for `data`. Any other failure, like a UART timeout, is `FormatError::Write` with an
+/// error code of the writer's own, so callers can tell a full buffer from a failing device.
+/// Formatting stops at the first error, and `kwrite!` returns it unchanged.
 pub trait Writeable {
-    fn write(&mut self, data: char) -> Result<(), usize>;
+    fn write(&mut self, data: char) -> Result<(), FormatError>;
+
+    /// Writes a whole string, override it when the writer can do better than one `write` per char.
+    fn write_str(&mut self, data: &str) -> Result<(), FormatError> {
+        for c in data.chars() {
+            self.write(c)?;
+        }
+        Ok(())
+    }
 }
 
-pub trait Formattable {
-    fn write_format(
-        &self,
-        writer: &mut dyn Writeable,
+/// Error returned by writers and formatting functions, and so by `kwrite!`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormatError {
+    /// The writer failed, with its own error code
+    Write(usize),
+    /// The writer has no room left
+    Overflow,
+    /// The spec cannot be applied to the value, like a runtime radix outside `2..=36`. Integers and
+    /// the debug builders check it before writing, so the failing placeholder writes nothing.
+    /// Hints written in the format string are checked at compile time.
+    InvalidSpec,
+}
+
+/// Formatting options of a single placeholder, built by `kwrite!` from the format hints.
+///
+/// Fields may be added in the future, so construct it with `..FormatSpec::new()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatSpec {
+    /// Character used to pad the value up to `width`, `f` hint
+    pub fill: char,
+    /// `<`, `^` and `>` hints
+    pub align: Option<Alignment>,
+    /// `+` hint, always print the sign of numbers
+    pub sign_plus: bool,
+    /// `' '` hint, print a space in place of the sign of positive numbers
+    pub sign_space: bool,
+    /// `0` hint, pad numbers with zeros up to `width`, between the sign and the digits
+    pub zero_pad: bool,
+    /// `#` hint
+    pub alternate: bool,
+    /// `w` hint
+    pub width: Option<usize>,
+    /// `p` hint
+    pub precision: Option<usize>,
+    /// `r`, `x`, `o` and `b` hints, integers fail with `InvalidSpec` outside `2..=36`
+    pub radix: Option<usize>,
+    /// `Some(true)` for the uppercase hints, `Some(false)` for the lowercase hints
+    pub case: Option<bool>,
+    /// `e` and `E` hints, scientific notation for floats
+    pub exponent: bool,
+    /// `...` hint, strings truncated by `precision` end with `...`
+    pub ellipsis: bool,
+    /// `g` hint, separator written between groups of digits of integers
+    pub grouping: Option<char>,
+}
+
+impl FormatSpec {
+    pub const fn new() -> FormatSpec {
+        FormatSpec {
+            fill: ' ',
+            align: None,
+            sign_plus: false,
+            sign_space: false,
+            zero_pad: false,
+            alternate: false,
+            width: None,
+            precision: None,
+            radix: None,
+            case: None,
+            exponent: false,
+            ellipsis: false,
+            grouping: None,
+        }
+    }
+
+    /// Builds a spec from the hint parameters `write_format` and `write_debug` used to take,
+    /// for code written against the previous API.
+    pub const fn from_hints(
         hint_pretty: Option<bool>,
         hint_radix: Option<usize>,
         hint_width: Option<usize>,
         hint_precision: Option<usize>,
         hint_case: Option<bool>,
-    ) -> Result<usize, usize>;
+    ) -> FormatSpec {
+        FormatSpec {
+            alternate: matches!(hint_pretty, Some(true)),
+            radix: hint_radix,
+            width: hint_width,
+            precision: hint_precision,
+            case: hint_case,
+            ..FormatSpec::new()
+        }
+    }
+}
+
+impl Default for FormatSpec {
+    fn default() -> Self {
+        FormatSpec::new()
+    }
+}
+
+pub trait Formattable {
+    fn write_format(
+        &self,
+        writer: &mut dyn Writeable,
+        spec: &FormatSpec,
+    ) -> Result<usize, FormatError>;
+
+    /// Alignment used when a width is given without an alignment hint.
+    fn default_alignment(&self) -> Alignment {
+        Alignment::Left
+    }
+
+    /// Called by `kwrite!`, pads the output of `write_format` to the width hint.
+    fn write_format_aligned(
+        &self,
+        writer: &mut dyn Writeable,
+        spec: &FormatSpec,
+    ) -> Result<usize, FormatError> {
+        write_aligned(writer, spec, self.default_alignment(), &|writer, spec| {
+            self.write_format(writer, spec)
+        })
+    }
 }
 
 pub trait Debuggable {
     fn write_debug(
         &self,
         writer: &mut dyn Writeable,
-        hint_pretty: Option<bool>,
-        hint_radix: Option<usize>,
-        hint_width: Option<usize>,
-        hint_precision: Option<usize>,
-        hint_case: Option<bool>,
-    ) -> Result<usize, usize>;
+        spec: &FormatSpec,
+    ) -> Result<usize, FormatError>;
+
+    /// Alignment used when a width is given without an alignment hint.
+    fn default_alignment(&self) -> Alignment {
+        Alignment::Left
+    }
+
+    /// Called by `kwrite!`, pads the output of `write_debug` to the width hint.
+    fn write_debug_aligned(
+        &self,
+        writer: &mut dyn Writeable,
+        spec: &FormatSpec,
+    ) -> Result<usize, FormatError> {
+        write_aligned(
+            writer,
+            spec,
+            Debuggable::default_alignment(self),
+            &|writer, spec| self.write_debug(writer, spec),
+        )
+    }
+}
+
+struct CountingWriter;
+
+impl Writeable for CountingWriter {
+    fn write(&mut self, _data: char) -> Result<(), FormatError> {
+        Ok(())
+    }
+}
+
+/// Writes the output of `write` padded with `spec.fill` up to `spec.width`, aligned with
+/// `spec.align` or `default_align`. `write` receives the spec without its width.
+/// When padding is needed, `write` is called twice: once to measure, once to write.
+pub fn write_aligned(
+    writer: &mut dyn Writeable,
+    spec: &FormatSpec,
+    default_align: Alignment,
+    write: &dyn Fn(&mut dyn Writeable, &FormatSpec) -> Result<usize, FormatError>,
+) -> Result<usize, FormatError> {
+    let inner = FormatSpec {
+        width: None,
+        ..*spec
+    };
+    let width = match spec.width {
+        Some(width) => width,
+        None => return write(writer, &inner),
+    };
+
+    let padding = width.saturating_sub(write(&mut CountingWriter, &inner)?);
+    let (before, after) = match spec.align.unwrap_or(default_align) {
+        Alignment::Left => (0, padding),
+        Alignment::Right => (padding, 0),
+        Alignment::Center => (padding / 2, padding - padding / 2),
+    };
+
+    for _ in 0..before {
+        writer.write(spec.fill)?;
+    }
+    let count = write(writer, &inner)?;
+    for _ in 0..after {
+        writer.write(spec.fill)?;
+    }
+
+    Ok(before + count + after)
+}
+
+/// Writer indenting every line by four spaces, used for the nested values of the pretty (`#`)
+/// output. `written` counts the chars given to the inner writer, indentation included.
+pub struct IndentWriter<'a> {
+    writer: &'a mut dyn Writeable,
+    on_newline: bool,
+    pub written: usize,
+}
+
+impl<'a> IndentWriter<'a> {
+    pub fn new(writer: &'a mut dyn Writeable) -> IndentWriter<'a> {
+        IndentWriter {
+            writer,
+            on_newline: true,
+            written: 0,
+        }
+    }
+}
+
+impl Writeable for IndentWriter<'_> {
+    fn write(&mut self, data: char) -> Result<(), FormatError> {
+        if self.on_newline {
+            self.writer.write_str("    ")?;
+            self.written += 4;
+        }
+        self.writer.write(data)?;
+        self.written += 1;
+        self.on_newline = data == '\n';
+        Ok(())
+    }
 }
 
 impl<T> Writeable for &mut T
 where
     T: Writeable,
 {
-    fn write(&mut self, data: char) -> Result<(), usize> {
+    fn write(&mut self, data: char) -> Result<(), FormatError> {
         (**self).write(data)
     }
+
+    fn write_str(&mut self, data: &str) -> Result<(), FormatError> {
+        (**self).write_str(data)
+    }
 }
 
 impl Formattable for char {
     fn write_format(
         &self,
         writer: &mut dyn Writeable,
-        _hint_pretty: Option<bool>,
-        _hint_radix: Option<usize>,
-        _hint_width: Option<usize>,
-        _hint_precision: Option<usize>,
-        _hint_case: Option<bool>,
-    ) -> Result<usize, usize> {
+        spec: &FormatSpec,
+    ) -> Result<usize, FormatError> {
+        if spec.precision.is_some() {
+            return Formattable::write_format(&*self.encode_utf8(&mut [0; 4]), writer, spec);
+        }
         writer.write(*self)?;
         Ok(1)
     }
 }
 
+/// The precision hint truncates the string to that many chars, the last three being replaced by
+/// `...` with the ellipsis hint.
 impl Formattable for str {
     fn write_format(
         &self,
         writer: &mut dyn Writeable,
-        _hint_pretty: Option<bool>,
-        _hint_radix: Option<usize>,
-        _hint_width: Option<usize>,
-        _hint_precision: Option<usize>,
-        _hint_case: Option<bool>,
-    ) -> Result<usize, usize> {
-        let mut count = 0;
-        for c in self.chars() {
-            writer.write(c)?;
-            count += 1;
-        }
-        Ok(count)
+        spec: &FormatSpec,
+    ) -> Result<usize, FormatError> {
+        let precision = match spec.precision {
+            Some(precision) if self.chars().nth(precision).is_some() => precision,
+            _ => {
+                writer.write_str(self)?;
+                return Ok(self.chars().count());
+            }
+        };
+        let (kept, dots) = if spec.ellipsis {
+            (precision.saturating_sub(3), precision.min(3))
+        } else {
+            (precision, 0)
+        };
+        let end = self.char_indices().nth(kept).map_or(self.len(), |(i, _)| i);
+        writer.write_str(&self[..end])?;
+        writer.write_str(&"..."[..dots])?;
+        Ok(kept + dots)
     }
 }
 
-impl Formattable for &str {
-    fn write_format(
+/// Quoted and escaped like `core::fmt::Debug`: `'\n'`, `'\''`, `'\u{7f}'`.
+impl Debuggable for char {
+    fn write_debug(
         &self,
         writer: &mut dyn Writeable,
-        _hint_pretty: Option<bool>,
-        _hint_radix: Option<usize>,
-        _hint_width: Option<usize>,
-        _hint_precision: Option<usize>,
-        _hint_case: Option<bool>,
-    ) -> Result<usize, usize> {
-        let mut count = 0;
-        for c in self.chars() {
-            writer.write(c)?;
-            count += 1;
-        }
-        Ok(count)
+        _spec: &FormatSpec,
+    ) -> Result<usize, FormatError> {
+        write_core_fmt(writer, format_args!("{:?}", self))
     }
 }
 
-impl<T: Formattable> Formattable for &mut T {
-    fn write_format(
+/// Quoted and escaped like `core::fmt::Debug`: `"a\"b\n"`, control and non-printable
+/// characters are written as `\u{..}`.
+impl Debuggable for str {
+    fn write_debug(
         &self,
         writer: &mut dyn Writeable,
-        hint_pretty: Option<bool>,
-        hint_radix: Option<usize>,
-        hint_width: Option<usize>,
-        hint_precision: Option<usize>,
-        hint_case: Option<bool>,
-    ) -> Result<usize, usize> {
-        (**self).write_format(
-            writer,
-            hint_pretty,
-            hint_radix,
-            hint_width,
-            hint_precision,
-            hint_case,
-        )
+        _spec: &FormatSpec,
+    ) -> Result<usize, FormatError> {
+        write_core_fmt(writer, format_args!("{:?}", self))
     }
 }
 
-impl<T: Formattable> Formattable for &T {
+impl<T: Formattable + ?Sized> Formattable for &mut T {
+    fn default_alignment(&self) -> Alignment {
+        (**self).default_alignment()
+    }
+
     fn write_format(
         &self,
         writer: &mut dyn Writeable,
-        hint_pretty: Option<bool>,
-        hint_radix: Option<usize>,
-        hint_width: Option<usize>,
-        hint_precision: Option<usize>,
-        hint_case: Option<bool>,
-    ) -> Result<usize, usize> {
-        (**self).write_format(
-            writer,
-            hint_pretty,
-            hint_radix,
-            hint_width,
-            hint_precision,
-            hint_case,
-        )
+        spec: &FormatSpec,
+    ) -> Result<usize, FormatError> {
+        (**self).write_format(writer, spec)
     }
 }
 
-#[macro_export]
-macro_rules! kwrite {
-    ($writer: ident, $fmt: literal, $($args:expr),*) => {{
-        use kformat_macros::kwrite_to_raw;
-        #[allow(unused_imports)]
-        use $crate::{Formattable, Debuggable, Writeable};
-        kwrite_to_raw!($writer, Formattable, write_format, Debuggable, write_debug, Writeable, usize, $fmt, $($args),*)
-    }};
-}
+impl<T: Formattable + ?Sized> Formattable for &T {
+    fn default_alignment(&self) -> Alignment {
+        (**self).default_alignment()
+    }
 
-#[macro_export]
-macro_rules! impl_formattable_int_type {
-    ($int_type_u: ident, $int_type_i: ident) => {
-        impl Formattable for $int_type_i {
-            fn write_format(
-                &self,
-                writer: &mut dyn Writeable,
-                hint_pretty: Option<bool>,
-                hint_radix: Option<usize>,
-                hint_width: Option<usize>,
-                hint_precision: Option<usize>,
-                hint_case: Option<bool>,
-            ) -> Result<usize, usize> {
-                let mut count = 0;
-                let mut buffer = ['\0'; 256];
-                let mut idx = 0;
-                let radix = match hint_radix {
-                    Some(hint_radix) => hint_radix,
-                    None => 10,
-                } as $int_type_u;
-                let alphabet = match hint_case {
-                    Some(true) => "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ",
-                    _ => "0123456789abcdefghijklmnopqrstuvwxyz",
-                };
-                let negative = *self < 0;
-
-                // Handle zero case
-                if *self == 0 {
-                    if let Some(precision) = hint_precision {
-                        while idx < precision {
-                            buffer[idx] = '0';
-                            idx += 1;
-                        }
-                    } else {
-                        buffer[idx] = '0';
-                        idx += 1;
-                    }
-                } else {
-                    // Convert the integer to the desired radix
-                    let mut value = self.unsigned_abs();
-                    while value > 0 {
-                        let remainder = (value % radix) as usize;
-                        buffer[idx] = alphabet.chars().nth(remainder).unwrap();
-                        value /= radix;
-                        idx += 1;
-                    }
-                }
+    fn write_format(
+        &self,
+        writer: &mut dyn Writeable,
+        spec: &FormatSpec,
+    ) -> Result<usize, FormatError> {
+        (**self).write_format(writer, spec)
+    }
+}
 
-                let precision = hint_precision.unwrap_or(idx);
-                while idx < precision {
-                    buffer[idx] = '0';
-                    idx += 1;
-                }
+impl<T: Debuggable + ?Sized> Debuggable for &mut T {
+    fn default_alignment(&self) -> Alignment {
+        Debuggable::default_alignment(&**self)
+    }
 
-                if hint_pretty.unwrap_or(false) {
-                    match radix {
-                        2 => {
-                            buffer[idx] = 'b';
-                            idx += 1;
-                            buffer[idx] = '0';
-                            idx += 1;
-                        }
-                        8 => {
-                            buffer[idx] = 'o';
-                            idx += 1;
-                            buffer[idx] = '0';
-                            idx += 1;
-                        }
-                        16 => {
-                            buffer[idx] = 'x';
-                            idx += 1;
-                            buffer[idx] = '0';
-                            idx += 1;
-                        }
-                        _ => {}
-                    }
-                }
+    fn write_debug(
+        &self,
+        writer: &mut dyn Writeable,
+        spec: &FormatSpec,
+    ) -> Result<usize, FormatError> {
+        (**self).write_debug(writer, spec)
+    }
+}
 
-                if negative {
-                    buffer[idx] = '-';
-                    idx += 1;
-                }
+impl<T: Debuggable + ?Sized> Debuggable for &T {
+    fn default_alignment(&self) -> Alignment {
+        Debuggable::default_alignment(&**self)
+    }
 
-                // Reverse the buffer for the correct order
-                let formatted_len = idx;
-                let mut formatted_buffer = ['\0'; 256]; // Use a second buffer for the reversed result
+    fn write_debug(
+        &self,
+        writer: &mut dyn Writeable,
+        spec: &FormatSpec,
+    ) -> Result<usize, FormatError> {
+        (**self).write_debug(writer, spec)
+    }
+}
 
-                for i in 0..formatted_len {
-                    formatted_buffer[i] = buffer[formatted_len - i - 1];
-                }
+kformat_macros::define_kformat! {
+    crate = crate,
+    format_trait = crate::Formattable,
+    format_fn = write_format_aligned,
+    debug_trait = crate::Debuggable,
+    debug_fn = write_debug_aligned,
+    writer = crate::Writeable,
+    error = crate::FormatError,
+    spec = crate::FormatSpec,
+}
 
-                // Apply width and precision formatting
-                let width = hint_width.unwrap_or(0);
-                let padding = if idx.max(precision) < width {
-                    width - idx.max(precision)
-                } else {
-                    0
-                };
+/// Writes an integer from its ASCII digits: the sign, the radix prefix with
+/// the alternate hint, zeros up to `precision` digits, and with the `0` hint, zeros up to `width`.
+/// With the grouping hint, the digits and zeros are grouped by 4 in binary and hexadecimal, by 3
+/// in the other radixes.
+#[doc(hidden)]
+pub fn write_int_digits(
+    writer: &mut dyn Writeable,
+    spec: &FormatSpec,
+    negative: bool,
+    radix: usize,
+    digits: &[u8],
+) -> Result<usize, FormatError> {
+    let sign = if negative {
+        "-"
+    } else if spec.sign_plus {
+        "+"
+    } else if spec.sign_space {
+        " "
+    } else {
+        ""
+    };
+    let prefix = match (spec.alternate, radix) {
+        (true, 2) => "0b",
+        (true, 8) => "0o",
+        (true, 16) => "0x",
+        _ => "",
+    };
+    let group_size = match radix {
+        2 | 16 => 4,
+        _ => 3,
+    };
+    // Length of `count` digits, separators included
+    let grouped_len = |count: usize| match spec.grouping {
+        Some(_) if count > 0 => count + (count - 1) / group_size,
+        _ => count,
+    };
 
-                // Add padding for width
-                for _ in 0..padding {
-                    writer.write(' ')?;
-                    count += 1;
-                }
+    let width = spec.width.unwrap_or(0);
+    let mut count = digits.len().max(spec.precision.unwrap_or(0));
+    if spec.zero_pad {
+        while sign.len() + prefix.len() + grouped_len(count) < width {
+            count += 1;
+        }
+    }
+    let len = sign.len() + prefix.len() + grouped_len(count);
+    let padding = width.saturating_sub(len);
 
-                // Write the formatted string to the writer
-                for &ch in formatted_buffer.iter().take(formatted_len) {
-                    writer.write(ch)?;
-                    count += 1;
-                }
+    for _ in 0..padding {
+        writer.write(spec.fill)?;
+    }
+    writer.write_str(sign)?;
+    writer.write_str(prefix)?;
+    // `i` is the position of the digit from the right, the missing digits are leading zeros
+    for i in (0..count).rev() {
+        let digit = if i < digits.len() {
+            digits[digits.len() - 1 - i]
+        } else {
+            b'0'
+        };
+        writer.write(digit as char)?;
+        match spec.grouping {
+            Some(separator) if i > 0 && i % group_size == 0 => writer.write(separator)?,
+            _ => {}
+        }
+    }
+    Ok(padding + len)
+}
 
-                Ok(count)
+#[macro_export]
+macro_rules! impl_formattable_int_type {
+    ($int_type_u: ident, $int_type_i: ident) => {
+        $crate::impl_formattable_int_type!(@impl $int_type_u, $int_type_u, |value: &$int_type_u| {
+            (false, *value)
+        });
+        $crate::impl_formattable_int_type!(@impl $int_type_i, $int_type_u, |value: &$int_type_i| {
+            (*value < 0, value.unsigned_abs())
+        });
+    };
+    // `$split` returns whether the value is negative, and its magnitude
+    (@impl $int_type: ident, $int_type_u: ident, $split: expr) => {
+        impl $crate::Formattable for $int_type {
+            fn default_alignment(&self) -> ::core::fmt::Alignment {
+                ::core::fmt::Alignment::Right
             }
-        }
 
-        impl Debuggable for $int_type_i {
-            fn write_debug(
-                &self,
-                writer: &mut dyn Writeable,
-                hint_pretty: Option<bool>,
-                hint_radix: Option<usize>,
-                hint_width: Option<usize>,
-                hint_precision: Option<usize>,
-                hint_case: Option<bool>,
-            ) -> Result<usize, usize> {
-                self.write_format(
-                    writer,
-                    hint_pretty,
-                    hint_radix,
-                    hint_width,
-                    hint_precision,
-                    hint_case,
-                )
-            }
-        }
-
-        impl Formattable for $int_type_u {
             fn write_format(
                 &self,
-                writer: &mut dyn Writeable,
-                hint_pretty: Option<bool>,
-                hint_radix: Option<usize>,
-                hint_width: Option<usize>,
-                hint_precision: Option<usize>,
-                hint_case: Option<bool>,
-            ) -> Result<usize, usize> {
-                let mut count = 0;
-                let mut buffer = ['\0'; 256];
-                let mut idx = 0;
-                let radix = match hint_radix {
-                    Some(hint_radix) => hint_radix,
+                writer: &mut dyn $crate::Writeable,
+                spec: &$crate::FormatSpec,
+            ) -> ::core::result::Result<usize, $crate::FormatError> {
+                // One digit per bit in binary, the longest output
+                let mut buffer = [0u8; <$int_type_u>::BITS as usize];
+                let mut idx = buffer.len();
+                let radix = match spec.radix {
+                    Some(radix) => radix,
                     None => 10,
-                } as $int_type_u;
-                let alphabet = match hint_case {
-                    Some(true) => "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ",
-                    _ => "0123456789abcdefghijklmnopqrstuvwxyz",
                 };
-
-                // Handle zero case
-                if *self == 0 {
-                    if let Some(precision) = hint_precision {
-                        while idx < precision {
-                            buffer[idx] = '0';
-                            idx += 1;
-                        }
-                    } else {
-                        buffer[idx] = '0';
-                        idx += 1;
-                    }
-                } else {
-                    // Convert the integer to the desired radix
-                    let mut value = *self;
-                    while value > 0 {
-                        let remainder = (value % radix) as usize;
-                        buffer[idx] = alphabet.chars().nth(remainder).unwrap();
-                        value /= radix;
-                        idx += 1;
-                    }
+                if !(2..=36).contains(&radix) {
+                    return ::core::result::Result::Err($crate::FormatError::InvalidSpec);
                 }
+                let alphabet = match spec.case {
+                    Some(true) => b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ",
+                    _ => b"0123456789abcdefghijklmnopqrstuvwxyz",
+                };
+                let (negative, mut value): (bool, $int_type_u) = ($split)(self);
 
-                let precision = hint_precision.unwrap_or(idx);
-                while idx < precision {
-                    buffer[idx] = '0';
-                    idx += 1;
+                // Zero has no digit of its own when a precision is given
+                if value == 0 && spec.precision.is_none() {
+                    idx -= 1;
+                    buffer[idx] = b'0';
                 }
-
-                if hint_pretty.unwrap_or(false) {
-                    match radix {
-                        2 => {
-                            buffer[idx] = 'b';
-                            idx += 1;
-                            buffer[idx] = '0';
-                            idx += 1;
-                        }
-                        8 => {
-                            buffer[idx] = 'o';
-                            idx += 1;
-                            buffer[idx] = '0';
-                            idx += 1;
-                        }
-                        16 => {
-                            buffer[idx] = 'x';
-                            idx += 1;
-                            buffer[idx] = '0';
-                            idx += 1;
-                        }
-                        _ => {}
-                    }
+                // Digits are written from the end, so the buffer ends up in reading order
+                while value > 0 {
+                    idx -= 1;
+                    buffer[idx] = alphabet[(value % radix as $int_type_u) as usize];
+                    value /= radix as $int_type_u;
                 }
 
-                // Reverse the buffer for the correct order
-                let formatted_len = idx;
-                let mut formatted_buffer = ['\0'; 256]; // Use a second buffer for the reversed result
-
-                for i in 0..formatted_len {
-                    formatted_buffer[i] = buffer[formatted_len - i - 1];
-                }
+                $crate::write_int_digits(writer, spec, negative, radix, &buffer[idx..])
+            }
 
-                // Apply width and precision formatting
-                let width = hint_width.unwrap_or(0);
-                let padding = if idx.max(precision) < width {
-                    width - idx.max(precision)
+            // Zero padding goes between the sign and the digits, not around the whole number
+            fn write_format_aligned(
+                &self,
+                writer: &mut dyn $crate::Writeable,
+                spec: &$crate::FormatSpec,
+            ) -> ::core::result::Result<usize, $crate::FormatError> {
+                if spec.zero_pad {
+                    $crate::Formattable::write_format(self, writer, spec)
                 } else {
-                    0
-                };
-
-                // Add padding for width
-                for _ in 0..padding {
-                    writer.write(' ')?;
-                    count += 1;
-                }
-
-                // Write the formatted string to the writer
-                for &ch in formatted_buffer.iter().take(formatted_len) {
-                    writer.write(ch)?;
-                    count += 1;
+                    $crate::write_aligned(
+                        writer,
+                        spec,
+                        ::core::fmt::Alignment::Right,
+                        &|writer, spec| $crate::Formattable::write_format(self, writer, spec),
+                    )
                 }
-
-                Ok(count)
             }
         }
 
-        impl Debuggable for $int_type_u {
+        impl $crate::Debuggable for $int_type {
+            fn default_alignment(&self) -> ::core::fmt::Alignment {
+                ::core::fmt::Alignment::Right
+            }
+
             fn write_debug(
                 &self,
-                writer: &mut dyn Writeable,
-                hint_pretty: Option<bool>,
-                hint_radix: Option<usize>,
-                hint_width: Option<usize>,
-                hint_precision: Option<usize>,
-                hint_case: Option<bool>,
-            ) -> Result<usize, usize> {
-                self.write_format(
-                    writer,
-                    hint_pretty,
-                    hint_radix,
-                    hint_width,
-                    hint_precision,
-                    hint_case,
-                )
+                writer: &mut dyn $crate::Writeable,
+                spec: &$crate::FormatSpec,
+            ) -> ::core::result::Result<usize, $crate::FormatError> {
+                $crate::Formattable::write_format(self, writer, spec)
+            }
+
+            fn write_debug_aligned(
+                &self,
+                writer: &mut dyn $crate::Writeable,
+                spec: &$crate::FormatSpec,
+            ) -> ::core::result::Result<usize, $crate::FormatError> {
+                $crate::Formattable::write_format_aligned(self, writer, spec)
             }
         }
     };
@@ -414,68 +574,491 @@ impl_formattable_int_type!(u64, i64);
 impl_formattable_int_type!(u128, i128);
 impl_formattable_int_type!(usize, isize);
 
-#[cfg(test)]
-mod nostd_tests {
-    use crate::Writeable;
-
-    struct Buffer {
-        data: [char; 1024],
-        len: usize,
-    }
+// Writes the output of `core::fmt` to a `Writeable`, counting the written chars
+struct FmtWriter<'a> {
+    writer: &'a mut dyn Writeable,
+    count: usize,
+    error: Option<FormatError>,
+}
 
-    impl Buffer {
-        fn new() -> Buffer {
-            Buffer {
-                data: ['\0'; 1024],
-                len: 0,
+impl core::fmt::Write for FmtWriter<'_> {
+    fn write_str(&mut self, data: &str) -> core::fmt::Result {
+        match self.writer.write_str(data) {
+            Ok(()) => {
+                self.count += data.chars().count();
+                Ok(())
+            }
+            Err(e) => {
+                self.error = Some(e);
+                Err(core::fmt::Error)
             }
         }
+    }
+}
 
-        fn get(&self) -> &[char] {
-            &self.data[0..self.len]
-        }
-
-        fn clear(&mut self) {
-            self.len = 0;
-        }
+// Writes `args` with `core::fmt`, for the types whose formatting is already exact in `core`
+fn write_core_fmt(
+    writer: &mut dyn Writeable,
+    args: core::fmt::Arguments,
+) -> Result<usize, FormatError> {
+    let mut out = FmtWriter {
+        writer,
+        count: 0,
+        error: None,
+    };
+    let _ = core::fmt::Write::write_fmt(&mut out, args);
+    match out.error {
+        Some(e) => Err(e),
+        None => Ok(out.count),
     }
+}
 
-    impl Writeable for Buffer {
-        fn write(&mut self, data: char) -> Result<(), usize> {
-            self.data[self.len] = data;
-            self.len += 1;
-            Ok(())
+// The digits come from the float formatting of `core`, which is exact: the shortest
+// representation that round-trips without a precision, correctly rounded with one.
+fn write_float_digits<T>(
+    writer: &mut dyn Writeable,
+    spec: &FormatSpec,
+    value: T,
+    debug: bool,
+) -> Result<usize, FormatError>
+where
+    T: core::fmt::Display + core::fmt::Debug + core::fmt::LowerExp + core::fmt::UpperExp,
+{
+    let upper = spec.case == Some(true);
+    match (spec.exponent, spec.precision) {
+        (false, None) if debug => write_core_fmt(writer, format_args!("{:?}", value)),
+        (false, None) => write_core_fmt(writer, format_args!("{}", value)),
+        (false, Some(precision)) => write_core_fmt(writer, format_args!("{:.*}", precision, value)),
+        (true, None) if upper => write_core_fmt(writer, format_args!("{:E}", value)),
+        (true, None) => write_core_fmt(writer, format_args!("{:e}", value)),
+        (true, Some(precision)) if upper => {
+            write_core_fmt(writer, format_args!("{:.*E}", precision, value))
         }
+        (true, Some(precision)) => write_core_fmt(writer, format_args!("{:.*e}", precision, value)),
     }
+}
 
-    #[test]
-    fn it_works() {
-        let mut buffer = Buffer::new();
-        buffer.write('a').unwrap();
-        buffer.write('b').unwrap();
-        buffer.write('c').unwrap();
-
-        assert_eq!(buffer.get(), &['a', 'b', 'c']);
-        buffer.clear();
+// `value` is the magnitude, its sign is written here so that the `0` hint pads between the sign
+// and the digits, like `core::fmt` does, `inf` and `NaN` included. NaN is never signed.
+fn write_float<T>(
+    writer: &mut dyn Writeable,
+    spec: &FormatSpec,
+    value: T,
+    negative: bool,
+    nan: bool,
+    debug: bool,
+) -> Result<usize, FormatError>
+where
+    T: Copy + core::fmt::Display + core::fmt::Debug + core::fmt::LowerExp + core::fmt::UpperExp,
+{
+    let sign = if nan {
+        ""
+    } else if negative {
+        "-"
+    } else if spec.sign_plus {
+        "+"
+    } else if spec.sign_space {
+        " "
+    } else {
+        ""
+    };
+    let zeros = match (spec.zero_pad, spec.width) {
+        (true, Some(width)) => width.saturating_sub(
+            sign.len() + write_float_digits(&mut CountingWriter, spec, value, debug)?,
+        ),
+        _ => 0,
+    };
 
-        assert_eq!(buffer.get(), &[]);
+    writer.write_str(sign)?;
+    for _ in 0..zeros {
+        writer.write('0')?;
     }
+    Ok(sign.len() + zeros + write_float_digits(writer, spec, value, debug)?)
+}
 
-    #[test]
-    fn it_works_with_format() {
-        let mut buffer = Buffer::new();
+macro_rules! impl_formattable_float_type {
+    ($float_type: ident) => {
+        impl Formattable for $float_type {
+            fn default_alignment(&self) -> Alignment {
+                Alignment::Right
+            }
 
-        kwrite!(buffer, "{} | {} | {}", 'a', 'b', "cd").unwrap();
+            fn write_format(
+                &self,
+                writer: &mut dyn Writeable,
+                spec: &FormatSpec,
+            ) -> Result<usize, FormatError> {
+                let negative = self.is_sign_negative();
+                let value = if negative { -*self } else { *self };
+                write_float(writer, spec, value, negative, self.is_nan(), false)
+            }
 
-        assert_eq!(
-            buffer.get(),
-            &['a', ' ', '|', ' ', 'b', ' ', '|', ' ', 'c', 'd']
-        );
-    }
+            // Zero padding goes between the sign and the digits, not around the whole number
+            fn write_format_aligned(
+                &self,
+                writer: &mut dyn Writeable,
+                spec: &FormatSpec,
+            ) -> Result<usize, FormatError> {
+                if spec.zero_pad {
+                    self.write_format(writer, spec)
+                } else {
+                    write_aligned(writer, spec, Alignment::Right, &|writer, spec| {
+                        self.write_format(writer, spec)
+                    })
+                }
+            }
+        }
 
-    #[test]
-    fn test_format_ints() {
-        let mut buffer = Buffer::new();
+        /// Same as `Formattable`, except that integral values keep a fractional part (`1.0`) and
+        /// very large or small values use scientific notation, like `core::fmt::Debug`.
+        impl Debuggable for $float_type {
+            fn default_alignment(&self) -> Alignment {
+                Alignment::Right
+            }
+
+            fn write_debug(
+                &self,
+                writer: &mut dyn Writeable,
+                spec: &FormatSpec,
+            ) -> Result<usize, FormatError> {
+                let negative = self.is_sign_negative();
+                let value = if negative { -*self } else { *self };
+                write_float(writer, spec, value, negative, self.is_nan(), true)
+            }
+
+            fn write_debug_aligned(
+                &self,
+                writer: &mut dyn Writeable,
+                spec: &FormatSpec,
+            ) -> Result<usize, FormatError> {
+                if spec.zero_pad {
+                    self.write_debug(writer, spec)
+                } else {
+                    write_aligned(writer, spec, Alignment::Right, &|writer, spec| {
+                        self.write_debug(writer, spec)
+                    })
+                }
+            }
+        }
+    };
+}
+
+impl_formattable_float_type!(f32);
+impl_formattable_float_type!(f64);
+
+// `(A, B)` written like `core::fmt::Debug` does, with the trait the tuple implements
+macro_rules! impl_formattable_tuple_types {
+    ($trait: ident, $write_fn: ident, $aligned_fn: ident, $(($($name: ident),+)),+) => {
+        $(
+            impl<$($name: $trait),+> $trait for ($($name,)+) {
+                #[allow(non_snake_case)]
+                fn $write_fn(&self, writer: &mut dyn Writeable, spec: &FormatSpec) -> Result<usize, FormatError> {
+                    let ($($name,)+) = self;
+                    debug_tuple(writer, spec, "")
+                        $(.field_with(&|writer, spec| $name.$aligned_fn(writer, spec)))+
+                        .finish()
+                }
+            }
+        )+
+    };
+}
+
+// The core types are written like `core::fmt::Debug` does for both traits, the nested values
+// being written with the same trait as the container
+macro_rules! impl_formattable_core_types {
+    ($trait: ident, $write_fn: ident, $aligned_fn: ident) => {
+        impl $trait for bool {
+            fn $write_fn(
+                &self,
+                writer: &mut dyn Writeable,
+                _spec: &FormatSpec,
+            ) -> Result<usize, FormatError> {
+                let text = if *self { "true" } else { "false" };
+                writer.write_str(text)?;
+                Ok(text.len())
+            }
+        }
+
+        impl $trait for () {
+            fn $write_fn(
+                &self,
+                writer: &mut dyn Writeable,
+                _spec: &FormatSpec,
+            ) -> Result<usize, FormatError> {
+                writer.write_str("()")?;
+                Ok(2)
+            }
+        }
+
+        impl $trait for Ordering {
+            fn $write_fn(
+                &self,
+                writer: &mut dyn Writeable,
+                _spec: &FormatSpec,
+            ) -> Result<usize, FormatError> {
+                let text = match self {
+                    Ordering::Less => "Less",
+                    Ordering::Equal => "Equal",
+                    Ordering::Greater => "Greater",
+                };
+                writer.write_str(text)?;
+                Ok(text.len())
+            }
+        }
+
+        /// `1.5s`, `10ms`, `1.000001µs` or `100ns`, the precision hint limits the fractional digits.
+        impl $trait for Duration {
+            fn $write_fn(
+                &self,
+                writer: &mut dyn Writeable,
+                spec: &FormatSpec,
+            ) -> Result<usize, FormatError> {
+                match spec.precision {
+                    Some(precision) => {
+                        write_core_fmt(writer, format_args!("{:.*?}", precision, self))
+                    }
+                    None => write_core_fmt(writer, format_args!("{:?}", self)),
+                }
+            }
+        }
+
+        impl<T: $trait> $trait for Option<T> {
+            fn $write_fn(
+                &self,
+                writer: &mut dyn Writeable,
+                spec: &FormatSpec,
+            ) -> Result<usize, FormatError> {
+                match self {
+                    Some(value) => debug_tuple(writer, spec, "Some")
+                        .field_with(&|writer, spec| value.$aligned_fn(writer, spec))
+                        .finish(),
+                    None => debug_tuple(writer, spec, "None").finish(),
+                }
+            }
+        }
+
+        impl<T: $trait, E: $trait> $trait for Result<T, E> {
+            fn $write_fn(
+                &self,
+                writer: &mut dyn Writeable,
+                spec: &FormatSpec,
+            ) -> Result<usize, FormatError> {
+                match self {
+                    Ok(value) => debug_tuple(writer, spec, "Ok")
+                        .field_with(&|writer, spec| value.$aligned_fn(writer, spec))
+                        .finish(),
+                    Err(error) => debug_tuple(writer, spec, "Err")
+                        .field_with(&|writer, spec| error.$aligned_fn(writer, spec))
+                        .finish(),
+                }
+            }
+        }
+
+        impl<T: $trait> $trait for [T] {
+            fn $write_fn(
+                &self,
+                writer: &mut dyn Writeable,
+                spec: &FormatSpec,
+            ) -> Result<usize, FormatError> {
+                let mut list = debug_list(writer, spec);
+                for value in self {
+                    list.entry_with(&|writer, spec| value.$aligned_fn(writer, spec));
+                }
+                list.finish()
+            }
+        }
+
+        impl<T: $trait, const N: usize> $trait for [T; N] {
+            fn $write_fn(
+                &self,
+                writer: &mut dyn Writeable,
+                spec: &FormatSpec,
+            ) -> Result<usize, FormatError> {
+                self.as_slice().$write_fn(writer, spec)
+            }
+        }
+
+        impl<T: $trait> $trait for Wrapping<T> {
+            fn default_alignment(&self) -> Alignment {
+                $trait::default_alignment(&self.0)
+            }
+
+            fn $write_fn(
+                &self,
+                writer: &mut dyn Writeable,
+                spec: &FormatSpec,
+            ) -> Result<usize, FormatError> {
+                self.0.$write_fn(writer, spec)
+            }
+        }
+
+        /// The address in hexadecimal, `0x1f00`.
+        impl<T: ?Sized> $trait for *const T {
+            fn default_alignment(&self) -> Alignment {
+                Alignment::Right
+            }
+
+            fn $write_fn(
+                &self,
+                writer: &mut dyn Writeable,
+                spec: &FormatSpec,
+            ) -> Result<usize, FormatError> {
+                let spec = FormatSpec {
+                    radix: Some(16),
+                    case: Some(false),
+                    alternate: true,
+                    ..*spec
+                };
+                (self.cast::<()>() as usize).$write_fn(writer, &spec)
+            }
+        }
+
+        impl<T: ?Sized> $trait for *mut T {
+            fn default_alignment(&self) -> Alignment {
+                Alignment::Right
+            }
+
+            fn $write_fn(
+                &self,
+                writer: &mut dyn Writeable,
+                spec: &FormatSpec,
+            ) -> Result<usize, FormatError> {
+                self.cast_const().$write_fn(writer, spec)
+            }
+        }
+
+        impl_formattable_nonzero_types!(
+            $trait,
+            $write_fn,
+            NonZeroU8,
+            NonZeroU16,
+            NonZeroU32,
+            NonZeroU64,
+            NonZeroU128,
+            NonZeroUsize,
+            NonZeroI8,
+            NonZeroI16,
+            NonZeroI32,
+            NonZeroI64,
+            NonZeroI128,
+            NonZeroIsize
+        );
+
+        impl_formattable_tuple_types!(
+            $trait,
+            $write_fn,
+            $aligned_fn,
+            (A),
+            (A, B),
+            (A, B, C),
+            (A, B, C, D),
+            (A, B, C, D, E),
+            (A, B, C, D, E, F),
+            (A, B, C, D, E, F, G),
+            (A, B, C, D, E, F, G, H),
+            (A, B, C, D, E, F, G, H, I),
+            (A, B, C, D, E, F, G, H, I, J),
+            (A, B, C, D, E, F, G, H, I, J, K),
+            (A, B, C, D, E, F, G, H, I, J, K, L)
+        );
+    };
+}
+
+macro_rules! impl_formattable_nonzero_types {
+    ($trait: ident, $write_fn: ident, $($nonzero_type: ident),+) => {
+        $(
+            impl $trait for $nonzero_type {
+                fn default_alignment(&self) -> Alignment {
+                    Alignment::Right
+                }
+
+                fn $write_fn(&self, writer: &mut dyn Writeable, spec: &FormatSpec) -> Result<usize, FormatError> {
+                    self.get().$write_fn(writer, spec)
+                }
+            }
+        )+
+    };
+}
+
+impl_formattable_core_types!(Formattable, write_format, write_format_aligned);
+impl_formattable_core_types!(Debuggable, write_debug, write_debug_aligned);
+
+#[cfg(test)]
+mod nostd_tests {
+    use crate::{kformat, kwrite, kwriteln, FormatError, FormatSpec, Formattable, Writeable};
+
+    struct Buffer {
+        data: [char; 1024],
+        len: usize,
+    }
+
+    impl Buffer {
+        fn new() -> Buffer {
+            Buffer {
+                data: ['\0'; 1024],
+                len: 0,
+            }
+        }
+
+        fn get(&self) -> &[char] {
+            &self.data[0..self.len]
+        }
+
+        fn clear(&mut self) {
+            self.len = 0;
+        }
+
+        fn is(&self, expected: &str) -> bool {
+            self.get().iter().copied().eq(expected.chars())
+        }
+    }
+
+    // Lets the tests compare against the output of `core::fmt`
+    impl core::fmt::Write for Buffer {
+        fn write_str(&mut self, data: &str) -> core::fmt::Result {
+            Writeable::write_str(self, data).map_err(|_| core::fmt::Error)
+        }
+    }
+
+    impl Writeable for Buffer {
+        fn write(&mut self, data: char) -> Result<(), FormatError> {
+            if self.len == self.data.len() {
+                return Err(FormatError::Overflow);
+            }
+            self.data[self.len] = data;
+            self.len += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn it_works() {
+        let mut buffer = Buffer::new();
+        buffer.write('a').unwrap();
+        buffer.write('b').unwrap();
+        buffer.write('c').unwrap();
+
+        assert_eq!(buffer.get(), &['a', 'b', 'c']);
+        buffer.clear();
+
+        assert_eq!(buffer.get(), &[]);
+    }
+
+    #[test]
+    fn it_works_with_format() {
+        let mut buffer = Buffer::new();
+
+        kwrite!(buffer, "{} | {} | {}", 'a', 'b', "cd").unwrap();
+
+        assert_eq!(
+            buffer.get(),
+            &['a', ' ', '|', ' ', 'b', ' ', '|', ' ', 'c', 'd']
+        );
+    }
+
+    #[test]
+    fn test_format_ints() {
+        let mut buffer = Buffer::new();
 
         kwrite!(buffer, "{}", -123).unwrap();
         assert_eq!(buffer.get(), &['-', '1', '2', '3']);
@@ -540,4 +1123,983 @@ mod nostd_tests {
         );
         buffer.clear();
     }
+
+    #[test]
+    fn test_format_arg_references() {
+        let mut buffer = Buffer::new();
+
+        kwrite!(buffer, "{1}{0}{1}", 'a', 'b').unwrap();
+        assert_eq!(buffer.get(), &['b', 'a', 'b']);
+        buffer.clear();
+
+        kwrite!(buffer, "{0:x}={0}", 26).unwrap();
+        assert_eq!(buffer.get(), &['1', 'a', '=', '2', '6']);
+        buffer.clear();
+
+        kwrite!(buffer, "{}{name:w3}{}", 'a', 'b', name = 7).unwrap();
+        assert_eq!(buffer.get(), &['a', ' ', ' ', '7', 'b']);
+        buffer.clear();
+
+        let mut evaluated = 0;
+        kwrite!(
+            buffer,
+            "{v}{v}",
+            v = {
+                evaluated += 1;
+                'z'
+            }
+        )
+        .unwrap();
+        assert_eq!(buffer.get(), &['z', 'z']);
+        assert_eq!(evaluated, 1);
+        buffer.clear();
+    }
+
+    #[test]
+    fn test_format_implicit_capture() {
+        let mut buffer = Buffer::new();
+
+        let irq = 33;
+        let name = "pit";
+        kwrite!(buffer, "{name}:{irq:x}:{}", irq).unwrap();
+        assert_eq!(buffer.get(), &['p', 'i', 't', ':', '2', '1', ':', '3', '3']);
+        buffer.clear();
+
+        kwrite!(buffer, "{irq}{other}", other = 'c').unwrap();
+        assert_eq!(buffer.get(), &['3', '3', 'c']);
+        buffer.clear();
+
+        let r#type = 'k';
+        kwrite!(buffer, "{r#type}").unwrap();
+        assert_eq!(buffer.get(), &['k']);
+        buffer.clear();
+
+        // Identifiers in the first slot are captured, hints other than the original ones need
+        // an empty first slot
+        let fd = 11;
+        let f = 'f';
+        kwrite!(buffer, "[{fd}][{f}][{:fd:w3}][{fd:fs:w4}]", 7).unwrap();
+        assert!(buffer.is("[11][f][dd7][ss11]"), "{:?}", buffer.get());
+        buffer.clear();
+
+        // `r`, `w` and `p` are hints only with their digits, named arguments come before locals
+        let p = 'p';
+        let n = 1;
+        kwrite!(buffer, "[{p}][{p2}][{n}][{n:w2}]", 1.25, n = 2).unwrap();
+        assert!(buffer.is("[p][1.25][2][ 2]"), "{:?}", buffer.get());
+        assert_eq!(n, 1);
+    }
+
+    #[test]
+    fn test_format_alignment() {
+        let mut buffer = Buffer::new();
+
+        kwrite!(buffer, "{w4}|{w4}", "ab", 'c').unwrap();
+        assert_eq!(buffer.get(), &['a', 'b', ' ', ' ', '|', 'c', ' ', ' ', ' ']);
+        buffer.clear();
+
+        kwrite!(buffer, "{w5:>:f*}", "ab").unwrap();
+        assert_eq!(buffer.get(), &['*', '*', '*', 'a', 'b']);
+        buffer.clear();
+
+        kwrite!(buffer, "{w5:^:f-}", "ab").unwrap();
+        assert_eq!(buffer.get(), &['-', 'a', 'b', '-', '-']);
+        buffer.clear();
+
+        kwrite!(buffer, "{w5:<}|{w5:f0:x}", 10, 255).unwrap();
+        assert_eq!(
+            buffer.get(),
+            &['1', '0', ' ', ' ', ' ', '|', '0', '0', '0', 'f', 'f']
+        );
+        buffer.clear();
+
+        kwrite!(buffer, "{w2:^}", "abc").unwrap();
+        assert_eq!(buffer.get(), &['a', 'b', 'c']);
+        buffer.clear();
+    }
+
+    #[test]
+    fn test_format_runtime_width_precision() {
+        let mut buffer = Buffer::new();
+
+        kwrite!(buffer, "{w*}|", 4, "ab").unwrap();
+        assert_eq!(buffer.get(), &['a', 'b', ' ', ' ', '|']);
+        buffer.clear();
+
+        kwrite!(buffer, "{w*:p*}", 5, 3, 7).unwrap();
+        assert_eq!(buffer.get(), &[' ', ' ', '0', '0', '7']);
+        buffer.clear();
+
+        let cols = 3;
+        kwrite!(buffer, "{w=cols}{0:w=1}", 'a', 2).unwrap();
+        assert_eq!(buffer.get(), &['a', ' ', ' ', 'a', ' ']);
+        buffer.clear();
+    }
+
+    #[test]
+    fn test_format_spec() {
+        struct SpecProbe;
+
+        impl Formattable for SpecProbe {
+            fn write_format(
+                &self,
+                writer: &mut dyn Writeable,
+                spec: &FormatSpec,
+            ) -> Result<usize, FormatError> {
+                let expected = FormatSpec {
+                    fill: '.',
+                    alternate: true,
+                    ..FormatSpec::from_hints(None, Some(16), None, Some(3), Some(true))
+                };
+                assert_eq!(spec, &expected);
+                writer.write('!')?;
+                Ok(1)
+            }
+        }
+
+        let mut buffer = Buffer::new();
+
+        kwrite!(buffer, "{#:X:p3:f.:w4}", SpecProbe).unwrap();
+        assert_eq!(buffer.get(), &['!', '.', '.', '.']);
+        buffer.clear();
+    }
+
+    #[test]
+    fn test_format_hint_combinations() {
+        let mut buffer = Buffer::new();
+
+        kwrite!(buffer, "{?:#:x}", 255).unwrap();
+        assert_eq!(buffer.get(), &['0', 'x', 'f', 'f']);
+        buffer.clear();
+
+        kwrite!(buffer, "{r36:U}", 35).unwrap();
+        assert_eq!(buffer.get(), &['Z']);
+        buffer.clear();
+    }
+
+    #[test]
+    fn test_format_escapes() {
+        let mut buffer = Buffer::new();
+
+        kwrite!(buffer, "%{{}%}%%", 'a').unwrap();
+        assert_eq!(buffer.get(), &['{', 'a', '}', '%']);
+        buffer.clear();
+
+        kwrite!(
+            #[kformat(escape = braces)]
+            buffer,
+            "{{{}}}%",
+            'a'
+        )
+        .unwrap();
+        assert_eq!(buffer.get(), &['{', 'a', '}', '%']);
+        buffer.clear();
+    }
+
+    #[test]
+    fn test_format_static_dispatch() {
+        let mut buffer = Buffer::new();
+
+        kwrite!(
+            #[kformat(dispatch = static)]
+            buffer,
+            "{} | {w5:x} | {?}",
+            "ab",
+            255u8,
+            -3
+        )
+        .unwrap();
+        assert_eq!(
+            buffer.get(),
+            &['a', 'b', ' ', '|', ' ', ' ', ' ', ' ', 'f', 'f', ' ', '|', ' ', '-', '3']
+        );
+        buffer.clear();
+
+        struct Full;
+
+        impl Writeable for Full {
+            fn write(&mut self, _data: char) -> Result<(), FormatError> {
+                Err(FormatError::Overflow)
+            }
+        }
+
+        let mut full = Full;
+        assert_eq!(
+            kwrite!(
+                #[kformat(dispatch = static)]
+                full,
+                "a{}",
+                'b'
+            ),
+            Err(FormatError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_format_bulk_text() {
+        struct Chunks {
+            buffer: Buffer,
+            chunks: usize,
+        }
+
+        impl Writeable for Chunks {
+            fn write(&mut self, data: char) -> Result<(), FormatError> {
+                self.chunks += 1;
+                self.buffer.write(data)
+            }
+
+            fn write_str(&mut self, data: &str) -> Result<(), FormatError> {
+                self.chunks += 1;
+                for c in data.chars() {
+                    self.buffer.write(c)?;
+                }
+                Ok(())
+            }
+        }
+
+        let mut chunks = Chunks {
+            buffer: Buffer::new(),
+            chunks: 0,
+        };
+
+        let count = kwrite!(chunks, "ab{}cd{}e", "-", 'x').unwrap();
+        assert_eq!(count, 7);
+        assert_eq!(chunks.buffer.get(), &['a', 'b', '-', 'c', 'd', 'x', 'e']);
+        assert_eq!(chunks.chunks, 3);
+    }
+
+    #[test]
+    fn test_format_writer_expressions() {
+        struct Console {
+            buffer: Buffer,
+        }
+
+        struct Guard<'a>(&'a mut Buffer);
+
+        impl core::ops::Deref for Guard<'_> {
+            type Target = Buffer;
+
+            fn deref(&self) -> &Buffer {
+                self.0
+            }
+        }
+
+        impl core::ops::DerefMut for Guard<'_> {
+            fn deref_mut(&mut self) -> &mut Buffer {
+                self.0
+            }
+        }
+
+        let mut console = Console {
+            buffer: Buffer::new(),
+        };
+        kwrite!(console.buffer, "{}", 1).unwrap();
+        assert_eq!(console.buffer.get(), &['1']);
+
+        let mut guard = Guard(&mut console.buffer);
+        kwrite!(*guard, "{}", 2).unwrap();
+        assert_eq!(guard.get(), &['1', '2']);
+
+        let mut evaluated = 0;
+        let mut buffers = [Buffer::new(), Buffer::new()];
+        kwrite!(
+            #[kformat(dispatch = static)]
+            buffers[{
+                evaluated += 1;
+                1
+            }],
+            "{}{}",
+            3,
+            4
+        )
+        .unwrap();
+        assert_eq!(buffers[1].get(), &['3', '4']);
+        assert_eq!(evaluated, 1);
+
+        kwrite!(&mut buffers[0], "{}", 5).unwrap();
+        assert_eq!(buffers[0].get(), &['5']);
+    }
+
+    #[test]
+    fn test_format_lines() {
+        let mut buffer = Buffer::new();
+
+        assert_eq!(kwrite!(buffer, "ok").unwrap(), 2);
+        assert_eq!(kwrite!(buffer, "{}", 1,).unwrap(), 1);
+        kwrite!(buffer, "{}{}", 2, 3,).unwrap();
+        assert_eq!(buffer.get(), &['o', 'k', '1', '2', '3']);
+
+        buffer.clear();
+        assert_eq!(kwriteln!(buffer).unwrap(), 1);
+        assert_eq!(kwriteln!(buffer, "a").unwrap(), 2);
+        kwriteln!(buffer, "{}", 4,).unwrap();
+        kwriteln!(
+            #[kformat(newline = "\r\n")]
+            buffer,
+            "{}",
+            5
+        )
+        .unwrap();
+        assert_eq!(buffer.get(), &['\n', 'a', '\n', '4', '\n', '5', '\r', '\n']);
+    }
+
+    #[test]
+    fn test_format_deferred() {
+        let mut buffer = Buffer::new();
+
+        let value = 7;
+        let line = kformat!("<{w3}>", value);
+        assert_eq!(line(&mut buffer).unwrap(), 5);
+        assert_eq!(kwrite!(buffer, "{}", 8,).unwrap(), 1);
+        line(&mut buffer).unwrap();
+        assert_eq!(
+            buffer.get(),
+            &['<', ' ', ' ', '7', '>', '8', '<', ' ', ' ', '7', '>']
+        );
+
+        buffer.clear();
+        let braces = kformat!(
+            #[kformat(escape = braces)]
+            "{{}}"
+        );
+        braces(&mut buffer).unwrap();
+        kwriteln!(buffer,).unwrap();
+        assert_eq!(buffer.get(), &['{', '}', '\n']);
+    }
+
+    #[test]
+    fn test_format_derive() {
+        use crate::Debuggable;
+
+        fn write_flag(
+            value: &bool,
+            writer: &mut dyn Writeable,
+            _spec: &FormatSpec,
+        ) -> Result<usize, FormatError> {
+            let text = if *value { "yes" } else { "no" };
+            writer.write_str(text)?;
+            Ok(text.len())
+        }
+
+        #[derive(Formattable, Debuggable)]
+        struct Point {
+            x: i32,
+            #[kformat(rename = "y_pos")]
+            y: i32,
+        }
+
+        #[derive(Debuggable)]
+        #[allow(dead_code)]
+        struct Wrapper(#[kformat(hex)] u32, #[kformat(skip)] u8, Point);
+
+        #[derive(Debuggable)]
+        enum Shape<T> {
+            Empty,
+            Dot(T),
+            Flag {
+                #[kformat(with = write_flag)]
+                set: bool,
+            },
+        }
+
+        let mut buffer = Buffer::new();
+
+        let count = kwrite!(buffer, "{}", Point { x: 1, y: -2 }).unwrap();
+        assert!(buffer.is("Point { x: 1, y_pos: -2 }"), "{:?}", buffer.get());
+        assert_eq!(count, 25);
+
+        buffer.clear();
+        let wrapper = Wrapper(255, 0, Point { x: 1, y: 2 });
+        kwrite!(buffer, "{?}", wrapper).unwrap();
+        assert!(
+            buffer.is("Wrapper(ff, Point { x: 1, y_pos: 2 })"),
+            "{:?}",
+            buffer.get()
+        );
+
+        buffer.clear();
+        let count = kwrite!(buffer, "{#:?}", wrapper).unwrap();
+        assert!(
+            buffer.is(
+                "Wrapper(\n    0xff,\n    Point {\n        x: 1,\n        y_pos: 2,\n    },\n)"
+            ),
+            "{:?}",
+            buffer.get()
+        );
+        assert_eq!(count, buffer.get().len());
+
+        buffer.clear();
+        kwrite!(
+            buffer,
+            "{?}|{w7:?}|{?}",
+            Shape::Dot(3),
+            Shape::<u8>::Empty,
+            Shape::<u8>::Flag { set: true }
+        )
+        .unwrap();
+        assert!(
+            buffer.is("Dot(3)|Empty  |Flag { set: yes }"),
+            "{:?}",
+            buffer.get()
+        );
+    }
+
+    #[test]
+    fn test_format_debug_builders() {
+        use crate::{debug_list, debug_map, Debuggable};
+
+        struct Table([(u8, u8); 2]);
+
+        impl Debuggable for Table {
+            fn write_debug(
+                &self,
+                writer: &mut dyn Writeable,
+                spec: &FormatSpec,
+            ) -> Result<usize, FormatError> {
+                debug_map(writer, spec)
+                    .entries(self.0.iter().map(|(key, value)| (*key, *value)))
+                    .entry(&3, &14)
+                    .finish()
+            }
+        }
+
+        struct Rows([u8; 2], Table);
+
+        impl Debuggable for Rows {
+            fn write_debug(
+                &self,
+                writer: &mut dyn Writeable,
+                spec: &FormatSpec,
+            ) -> Result<usize, FormatError> {
+                debug_list(writer, spec)
+                    .entries(self.0)
+                    .entry(&self.1)
+                    .finish()
+            }
+        }
+
+        struct Empty;
+
+        impl Debuggable for Empty {
+            fn write_debug(
+                &self,
+                writer: &mut dyn Writeable,
+                spec: &FormatSpec,
+            ) -> Result<usize, FormatError> {
+                debug_list(writer, spec).finish()
+            }
+        }
+
+        let rows = Rows([1, 2], Table([(10, 11), (12, 13)]));
+        let mut buffer = Buffer::new();
+
+        let count = kwrite!(buffer, "{?} {?}", rows, Empty).unwrap();
+        assert!(
+            buffer.is("[1, 2, {10: 11, 12: 13, 3: 14}] []"),
+            "{:?}",
+            buffer.get()
+        );
+        assert_eq!(count, buffer.get().len());
+
+        buffer.clear();
+        let count = kwrite!(buffer, "{#:?}", rows).unwrap();
+        assert!(
+            buffer.is("[\n    1,\n    2,\n    {\n        10: 11,\n        12: 13,\n        3: 14,\n    },\n]"),
+            "{:?}",
+            buffer.get()
+        );
+        assert_eq!(count, buffer.get().len());
+
+        // The first writer error is returned, and nothing is written after it
+        struct Full(usize);
+
+        impl Writeable for Full {
+            fn write(&mut self, _data: char) -> Result<(), FormatError> {
+                if self.0 == 0 {
+                    return Err(FormatError::Overflow);
+                }
+                self.0 -= 1;
+                Ok(())
+            }
+        }
+
+        let mut full = Full(5);
+        assert_eq!(kwrite!(full, "{?}", rows), Err(FormatError::Overflow));
+        assert_eq!(full.0, 0);
+
+        // Same with the pretty hint, when the opening bracket fails
+        struct Flaky {
+            buffer: Buffer,
+            failed: bool,
+        }
+
+        impl Writeable for Flaky {
+            fn write(&mut self, data: char) -> Result<(), FormatError> {
+                if data == '[' && !self.failed {
+                    self.failed = true;
+                    return Err(FormatError::Write(1));
+                }
+                self.buffer.write(data)
+            }
+        }
+
+        let mut flaky = Flaky {
+            buffer: Buffer::new(),
+            failed: false,
+        };
+        assert_eq!(
+            kwrite!(flaky, "{#:?}", [1u8, 2]),
+            Err(FormatError::Write(1))
+        );
+        assert!(flaky.buffer.is(""), "{:?}", flaky.buffer.get());
+    }
+
+    #[test]
+    fn test_format_floats() {
+        let mut buffer = Buffer::new();
+
+        kwrite!(buffer, "{} {} {} {:e}", 0.1 + 0.2, 0.1f32, 1e21, 5e-324).unwrap();
+        assert!(
+            buffer.is("0.30000000000000004 0.1 1000000000000000000000 5e-324"),
+            "{:?}",
+            buffer.get()
+        );
+
+        buffer.clear();
+
+        // `e` in the first slot is a captured argument, the hint needs an empty first slot
+        let e = 2.5;
+        kwrite!(buffer, "{e} {e:e} {:E}", 1.5).unwrap();
+        assert!(buffer.is("2.5 2.5e0 1.5E0"), "{:?}", buffer.get());
+
+        buffer.clear();
+        kwrite!(
+            buffer,
+            "{p2}|{p0}|{:e}|{:E:p2}|{w8}|{<:w6:p1}",
+            2.675,
+            2.5,
+            1234.5,
+            1234.5,
+            1.5,
+            -0.25
+        )
+        .unwrap();
+        assert!(
+            buffer.is("2.67|2|1.2345e3|1.23E3|     1.5|-0.2  "),
+            "{:?}",
+            buffer.get()
+        );
+
+        buffer.clear();
+        kwrite!(buffer, "{?} {?} {?} {}", 1.0, 1e20, 1e-7, 1.0).unwrap();
+        assert!(buffer.is("1.0 1e20 1e-7 1"), "{:?}", buffer.get());
+
+        buffer.clear();
+        kwrite!(
+            buffer,
+            "{} {} {} {} {w5}",
+            f64::NAN,
+            f64::INFINITY,
+            f32::NEG_INFINITY,
+            -0.0,
+            -0.0
+        )
+        .unwrap();
+        assert!(buffer.is("NaN inf -inf -0    -0"), "{:?}", buffer.get());
+
+        buffer.clear();
+        let spec = FormatSpec {
+            sign_plus: true,
+            ..FormatSpec::new()
+        };
+        for value in [1.5, 0.0, f64::NAN, f64::INFINITY, -2.0] {
+            value.write_format_aligned(&mut buffer, &spec).unwrap();
+            buffer.write(' ').unwrap();
+        }
+        assert!(buffer.is("+1.5 +0 NaN +inf -2 "), "{:?}", buffer.get());
+
+        // The `0` hint pads between the sign and the digits, like `core::fmt`
+        buffer.clear();
+        let mut expected = Buffer::new();
+        kwrite!(
+            buffer,
+            "[{0w8}][{0w8}][{+:0w8}][{0w8:p2}][{0w8:e}][{0w8}][{<:0w8}][{?:0w8}][{+:0w4}]",
+            f64::INFINITY,
+            f64::NAN,
+            1.5,
+            -2.345,
+            1234.5,
+            -0.0,
+            1.5f32,
+            1.0,
+            -f64::NAN
+        )
+        .unwrap();
+        core::fmt::Write::write_fmt(
+            &mut expected,
+            format_args!(
+                "[{:08}][{:08}][{:+08}][{:08.2}][{:08e}][{:08}][{:<08}][{:08?}][{:+04}]",
+                f64::INFINITY,
+                f64::NAN,
+                1.5,
+                -2.345,
+                1234.5,
+                -0.0,
+                1.5f32,
+                1.0,
+                -f64::NAN
+            ),
+        )
+        .unwrap();
+        assert_eq!(buffer.get(), expected.get());
+
+        buffer.clear();
+        kwrite!(buffer, "[{ :0w6}][{ :0w6}]", 2.5, -2.5).unwrap();
+        assert!(buffer.is("[ 002.5][-002.5]"), "{:?}", buffer.get());
+    }
+
+    #[test]
+    fn test_format_core_types() {
+        use core::{cmp::Ordering, num::NonZeroU16, num::Wrapping, time::Duration};
+
+        let mut buffer = Buffer::new();
+
+        let ok: Result<(u8, bool), i8> = Ok((1, true));
+        let err: Result<u8, [i8; 2]> = Err([-1, 2]);
+        kwrite!(
+            buffer,
+            "{?} {?} {?} {?} {?}",
+            Some(3),
+            None::<u8>,
+            ok,
+            err,
+            ()
+        )
+        .unwrap();
+        assert!(
+            buffer.is("Some(3) None Ok((1, true)) Err([-1, 2]) ()"),
+            "{:?}",
+            buffer.get()
+        );
+
+        buffer.clear();
+        let slice: &[u8] = &[4, 5];
+        let empty: [u8; 0] = [];
+        kwrite!(buffer, "{?} {?} {?} {}", slice, empty, (7,), (8u8, 9u8)).unwrap();
+        assert!(buffer.is("[4, 5] [] (7,) (8, 9)"), "{:?}", buffer.get());
+
+        buffer.clear();
+        kwrite!(
+            buffer,
+            "{w4} {x} {?} {?} {?} {p2:?} {?}",
+            NonZeroU16::new(12).unwrap(),
+            Wrapping(255u8),
+            Ordering::Less,
+            Duration::from_millis(1500),
+            Duration::from_micros(10),
+            Duration::from_nanos(1_234_567),
+            0x1f00 as *const u8
+        )
+        .unwrap();
+        assert!(
+            buffer.is("  12 ff Less 1.5s 10µs 1.23ms 0x1f00"),
+            "{:?}",
+            buffer.get()
+        );
+
+        buffer.clear();
+        let count = kwrite!(buffer, "{#:?}", Some([1, 2])).unwrap();
+        assert!(
+            buffer.is("Some(\n    [\n        1,\n        2,\n    ],\n)"),
+            "{:?}",
+            buffer.get()
+        );
+        assert_eq!(count, buffer.get().len());
+    }
+
+    #[test]
+    fn test_format_debug_escapes() {
+        let mut buffer = Buffer::new();
+
+        let text = "a\"b'\n\t\u{7f}\u{200b}é";
+        let count = kwrite!(buffer, "{?} {?} {?} {?} {}", text, '\'', '"', '\0', text).unwrap();
+        assert!(
+            buffer
+                .is("\"a\\\"b'\\n\\t\\u{7f}\\u{200b}é\" '\\'' '\"' '\\0' a\"b'\n\t\u{7f}\u{200b}é"),
+            "{:?}",
+            buffer.get()
+        );
+        assert_eq!(count, buffer.get().len());
+
+        buffer.clear();
+        kwrite!(buffer, "{?}", Some(("x", 'y'))).unwrap();
+        assert!(buffer.is("Some((\"x\", 'y'))"), "{:?}", buffer.get());
+    }
+
+    #[test]
+    fn test_format_string_truncation() {
+        let mut buffer = Buffer::new();
+
+        let count = kwrite!(
+            buffer,
+            "[{w6}][{>:w6}][{p3}][{p8:...}][{p5:...}][{w7:p2:...}][{p2}]",
+            "ab",
+            "cd",
+            "status",
+            "status",
+            "status",
+            "status",
+            "é"
+        )
+        .unwrap();
+        assert!(
+            buffer.is("[ab    ][    cd][sta][status][st...][..     ][é]"),
+            "{:?}",
+            buffer.get()
+        );
+        assert_eq!(count, buffer.get().len());
+
+        buffer.clear();
+        kwrite!(buffer, "[{p0}][{^:w5:p1}][{p3:...}]", 'x', "éè", "abcd").unwrap();
+        assert!(buffer.is("[][  é  ][...]"), "{:?}", buffer.get());
+    }
+
+    #[test]
+    fn test_format_int_signs() {
+        use core::fmt::Write;
+
+        let mut buffer = Buffer::new();
+        let mut expected = Buffer::new();
+
+        let count = kwrite!(
+            buffer,
+            "[{+}][{+}][{+}][{0w6}][{+:0w6}][{0w2}][{<:0w6}][{+:w6}][{#:0w10:x}][{0w8:b}][{#:0w6:o}]",
+            5,
+            -5,
+            0u8,
+            -42,
+            42i16,
+            -123,
+            7,
+            9u32,
+            0xbeefu32,
+            5u8,
+            8usize
+        )
+        .unwrap();
+        write!(
+            expected,
+            "[{:+}][{:+}][{:+}][{:06}][{:+06}][{:02}][{:<06}][{:+6}][{:#010x}][{:08b}][{:#06o}]",
+            5, -5, 0u8, -42, 42i16, -123, 7, 9u32, 0xbeefu32, 5u8, 8usize
+        )
+        .unwrap();
+        assert_eq!(buffer.get(), expected.get());
+        assert_eq!(count, buffer.get().len());
+
+        // Negative numbers keep their sign in every radix, instead of the two's complement
+        buffer.clear();
+        kwrite!(
+            buffer,
+            "[{ }][{ }][{ :0w5}][{#:0w8:x}][{0w8:x}][{0w4}][{0W4}]",
+            5,
+            -5i64,
+            7u16,
+            -31,
+            -31,
+            i8::MIN,
+            u128::MAX % 10
+        )
+        .unwrap();
+        assert!(
+            buffer.is("[ 5][-5][ 0007][-0x0001f][-000001f][-128][0005]"),
+            "{:?}",
+            buffer.get()
+        );
+
+        // The precision is a minimum number of digits, the zeros of the width come on top
+        buffer.clear();
+        kwrite!(
+            buffer,
+            "[{p3:0w6}][{+:p4}][{#:p4:w9:x}][{p0}]",
+            -5,
+            12,
+            255,
+            0
+        )
+        .unwrap();
+        assert!(
+            buffer.is("[-00005][+0012][   0x00ff][]"),
+            "{:?}",
+            buffer.get()
+        );
+    }
+
+    #[test]
+    fn test_format_int_grouping() {
+        let mut buffer = Buffer::new();
+
+        let count = kwrite!(
+            buffer,
+            "[{:g}][{:g}][{:g}][{:g_:#:x}][{:g:b}][{G':w10}][{:g:0w8}][{:g:p5}][{:g_:o}][{:g}][{:g:p0}]",
+            1234567,
+            -1000,
+            999u16,
+            0xdeadbeefu32,
+            0b1011_0000u8,
+            1234,
+            5,
+            12,
+            0o7654321,
+            u128::MAX,
+            0
+        )
+        .unwrap();
+        assert!(
+            buffer.is(concat!(
+                "[1,234,567][-1,000][999][0xdead_beef][1011,0000][     1'234][0,000,005]",
+                "[00,012][7_654_321][340,282,366,920,938,463,463,374,607,431,768,211,455][]"
+            )),
+            "{:?}",
+            buffer.get()
+        );
+        assert_eq!(count, buffer.get().len());
+
+        // Separators count in the width, the zero padding never starts with one
+        buffer.clear();
+        kwrite!(
+            buffer,
+            "[{:g:0w6}][{+:g:0w7}][{#:g_:0w11:X}]",
+            42,
+            42,
+            0xabcu16
+        )
+        .unwrap();
+        assert!(
+            buffer.is("[00,042][+00,042][0x0000_0ABC]"),
+            "{:?}",
+            buffer.get()
+        );
+
+        // A local named like a grouping hint is captured in the first slot
+        buffer.clear();
+        let g = 5;
+        let g_ = 6;
+        kwrite!(buffer, "[{g}][{g_}][{:g_}][{g:g:w6}]", 123456).unwrap();
+        assert!(buffer.is("[5][6][123_456][     5]"), "{:?}", buffer.get());
+    }
+
+    #[test]
+    fn test_format_int_buffers() {
+        let mut buffer = Buffer::new();
+
+        // Longest outputs of each width, in binary
+        kwrite!(buffer, "{b}|{b}|{b}", u8::MAX, i8::MIN, u128::MAX).unwrap();
+        let mut expected = Buffer::new();
+        core::fmt::Write::write_fmt(
+            &mut expected,
+            format_args!("{:b}|-{:b}|{:b}", u8::MAX, 128, u128::MAX),
+        )
+        .unwrap();
+        assert_eq!(buffer.get(), expected.get());
+
+        // Zeros of the precision are not buffered
+        buffer.clear();
+        let count = kwrite!(buffer, "{p600:x}", 0xabu8).unwrap();
+        assert_eq!(count, 600);
+        assert!(buffer.get()[..598].iter().all(|c| *c == '0'));
+        assert_eq!(buffer.get()[598..], ['a', 'b']);
+    }
+
+    #[test]
+    fn test_format_runtime_radix() {
+        let mut buffer = Buffer::new();
+
+        let radix = 36;
+        kwrite!(buffer, "{r*} {#:r=radix} {r=0:w4}", 2, 5u8, 35, 7i64).unwrap();
+        assert!(buffer.is("101 z  111"), "{:?}", buffer.get());
+
+        // Invalid radices fail before writing, even inside a padded placeholder
+        for radix in [0, 1, 37, 300] {
+            buffer.clear();
+            assert_eq!(
+                kwrite!(buffer, "{r*:w8}", radix, 5u8),
+                Err(FormatError::InvalidSpec)
+            );
+            assert_eq!(
+                kwrite!(buffer, "{r=radix}", i128::MIN),
+                Err(FormatError::InvalidSpec)
+            );
+            assert!(buffer.is(""), "{:?}", buffer.get());
+        }
+
+        // Containers fail before their brackets, the radix applying to their entries
+        buffer.clear();
+        assert_eq!(
+            kwrite!(buffer, "{r*:?}", 0, [1u8, 2]),
+            Err(FormatError::InvalidSpec)
+        );
+        assert_eq!(
+            kwrite!(buffer, "{r*:?}", 37, Some((1u8, 2))),
+            Err(FormatError::InvalidSpec)
+        );
+        assert!(buffer.is(""), "{:?}", buffer.get());
+
+        // Specs built by hand are checked too
+        let spec = FormatSpec {
+            radix: Some(0),
+            ..FormatSpec::new()
+        };
+        assert_eq!(
+            42usize.write_format(&mut buffer, &spec),
+            Err(FormatError::InvalidSpec)
+        );
+    }
+
+    #[test]
+    fn test_format_errors() {
+        let mut buffer = Buffer::new();
+
+        // A full buffer is told apart from a failing writer
+        assert_eq!(kwrite!(buffer, "{w2000}", "x"), Err(FormatError::Overflow));
+        assert_eq!(buffer.get().len(), 1024);
+
+        // Writers with a fixed capacity report a full buffer as `Overflow`
+        struct Line {
+            data: [char; 4],
+            len: usize,
+        }
+
+        impl Writeable for Line {
+            fn write(&mut self, data: char) -> Result<(), FormatError> {
+                let slot = self.data.get_mut(self.len).ok_or(FormatError::Overflow)?;
+                *slot = data;
+                self.len += 1;
+                Ok(())
+            }
+        }
+
+        let mut line = Line {
+            data: ['\0'; 4],
+            len: 0,
+        };
+        assert_eq!(kwrite!(line, "{}", 1234), Ok(4));
+        assert_eq!(kwrite!(line, "{}", 5), Err(FormatError::Overflow));
+        line.len = 0;
+        assert_eq!(kwrite!(line, "id {x}", 0xab), Err(FormatError::Overflow));
+        assert_eq!(line.data, ['i', 'd', ' ', 'a']);
+
+        struct Uart;
+
+        impl Writeable for Uart {
+            fn write(&mut self, _data: char) -> Result<(), FormatError> {
+                Err(FormatError::Write(0x54))
+            }
+        }
+
+        let error = match kwriteln!(Uart, "{}", 1) {
+            Err(FormatError::Write(code)) => code,
+            result => panic!("{:?}", result),
+        };
+        assert_eq!(error, 0x54);
+    }
 }
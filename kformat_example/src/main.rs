@@ -1,4 +1,10 @@
-use kformat_macros::kwrite_to_raw;
+kformat_macros::define_kformat! {
+    format_trait = crate::Formattable,
+    format_fn = write,
+    writer = crate::Writeable,
+    error = crate::FormatError,
+    spec = crate::FormatSpec,
+}
 
 #[derive(Debug)]
 enum FormatError {
@@ -7,16 +13,26 @@ enum FormatError {
 #[derive(Debug)]
 enum WriteError {}
 
+#[derive(Default)]
+#[allow(dead_code)]
+struct FormatSpec {
+    fill: char,
+    align: Option<core::fmt::Alignment>,
+    sign_plus: bool,
+    sign_space: bool,
+    zero_pad: bool,
+    alternate: bool,
+    width: Option<usize>,
+    precision: Option<usize>,
+    radix: Option<usize>,
+    case: Option<bool>,
+    exponent: bool,
+    ellipsis: bool,
+    grouping: Option<char>,
+}
+
 trait Formattable {
-    fn write(
-        &self,
-        writer: &mut dyn Writeable,
-        hint_pretty: Option<bool>,
-        hint_radix: Option<usize>,
-        hint_width: Option<usize>,
-        hint_precision: Option<usize>,
-        hint_case: Option<usize>,
-    ) -> Result<usize, FormatError>;
+    fn write(&self, writer: &mut dyn Writeable, spec: &FormatSpec) -> Result<usize, FormatError>;
 }
 
 trait Writeable {
@@ -33,15 +49,7 @@ impl Writeable for Console {
 }
 
 impl Formattable for &[char] {
-    fn write(
-        &self,
-        writer: &mut dyn Writeable,
-        _hint_pretty: Option<bool>,
-        _hint_radix: Option<usize>,
-        _hint_width: Option<usize>,
-        _hint_precision: Option<usize>,
-        _hint_case: Option<usize>,
-    ) -> Result<usize, FormatError> {
+    fn write(&self, writer: &mut dyn Writeable, _spec: &FormatSpec) -> Result<usize, FormatError> {
         for c in self.iter() {
             match writer.write(*c) {
                 Ok(_) => {}
@@ -53,15 +61,7 @@ impl Formattable for &[char] {
 }
 
 impl Formattable for &str {
-    fn write(
-        &self,
-        writer: &mut dyn Writeable,
-        _hint_pretty: Option<bool>,
-        _hint_radix: Option<usize>,
-        _hint_width: Option<usize>,
-        _hint_precision: Option<usize>,
-        _hint_case: Option<usize>,
-    ) -> Result<usize, FormatError> {
+    fn write(&self, writer: &mut dyn Writeable, _spec: &FormatSpec) -> Result<usize, FormatError> {
         let mut count = 0;
         for c in self.chars() {
             match writer.write(c) {
@@ -84,14 +84,8 @@ pub fn main() {
     let data2 = vec!['d', 'e', 'f'];
     let actual_data2 = &data2 as &[char];
 
-    let amount = kwrite_to_raw!(
+    let amount = kwrite!(
         console,
-        Formattable,
-        write,
-        Formattable,
-        write,
-        Writeable,
-        FormatError,
         "Test {?} format {#}\r\n",
         actual_data,
         actual_data2
@@ -99,4 +93,15 @@ pub fn main() {
     .unwrap();
 
     println!("Formatted into {} chars.", amount);
+
+    report::report(&mut console);
+}
+
+mod report {
+    use crate::kwrite;
+
+    pub fn report(console: &mut crate::Console) {
+        kwrite!(*console, "report\r\n").unwrap();
+        crate::kwriteln!(*console, "done").unwrap();
+    }
 }
@@ -1,106 +1,144 @@
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{parse::Parse, parse_macro_input, punctuated::Punctuated, Expr, Ident, LitStr, Token};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
 
-struct WriteSingleArgs {
-    value: Expr,
-    _comma1: Token![,],
-    value_trait: Ident,
+use proc_macro2::{Span, TokenTree};
+use quote::{format_ident, quote, ToTokens};
+use syn::{
+    ext::IdentExt, parse::Parse, parse_macro_input, punctuated::Punctuated, Attribute, Expr,
+    ExprLit, Ident, Lit, LitStr, Path, Token, Type,
+};
+
+struct WriteInput {
+    options: FormatOptions,
+    writer: Expr,
+    _comma: Token![,],
+    trait_format: Path,
     _comma2: Token![,],
-    value_write_fn: Ident,
+    format_fn: Ident,
     _comma3: Token![,],
-    writer: Expr,
+    trait_debug: Path,
     _comma4: Token![,],
-    writer_trait: Ident,
+    debug_fn: Ident,
     _comma5: Token![,],
-    hint_pretty: Expr,
+    trait_writeable: Path,
     _comma6: Token![,],
-    hint_radix: Expr,
+    error_type: Type,
     _comma7: Token![,],
-    hint_width: Expr,
+    spec_type: Path,
     _comma8: Token![,],
-    hint_precision: Expr,
-    _comma9: Token![,],
-    hint_case: Expr,
+    format: LitStr,
+    _comma9: Option<Token![,]>,
+    args: Punctuated<FormatArg, Token![,]>,
 }
 
-impl Parse for WriteSingleArgs {
-    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
-        Ok(Self {
-            value: input.parse()?,
-            _comma1: input.parse()?,
-            value_trait: input.parse()?,
-            _comma2: input.parse()?,
-            value_write_fn: input.parse()?,
-            _comma3: input.parse()?,
-            writer: input.parse()?,
-            _comma4: input.parse()?,
-            writer_trait: input.parse()?,
-            _comma5: input.parse()?,
-            hint_pretty: input.parse()?,
-            _comma6: input.parse()?,
-            hint_radix: input.parse()?,
-            _comma7: input.parse()?,
-            hint_width: input.parse()?,
-            _comma8: input.parse()?,
-            hint_precision: input.parse()?,
-            _comma9: input.parse()?,
-            hint_case: input.parse()?,
-        })
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EscapeMode {
+    // `%` escapes the next character, `{` and `}` always delimit placeholders
+    Percent,
+    // `{{` and `}}` escape braces, like `core::fmt`
+    Braces,
 }
 
-#[proc_macro]
-pub fn write_single(input: TokenStream) -> TokenStream {
-    let WriteSingleArgs {
-        value,
-        value_trait,
-        value_write_fn,
-        writer,
-        writer_trait,
-        hint_pretty,
-        hint_radix,
-        hint_width,
-        hint_precision,
-        hint_case,
-        ..
-    } = parse_macro_input!(input as WriteSingleArgs);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DispatchMode {
+    // Values and writer go through `&dyn` trait objects inside a `&dyn Fn` closure
+    Dynamic,
+    // Direct `Trait::method(value, writer, spec)` calls, without trait objects or closure
+    Static,
+}
 
-    let expanded = quote! {
-        {
-            let __value_as_trait: &dyn #value_trait = &#value;
-            let mut __writer = &mut #writer as &mut dyn #writer_trait;
+// Options given as `#[kformat(...)]` attributes before the writer
+struct FormatOptions {
+    escape: EscapeMode,
+    dispatch: DispatchMode,
+    // Line terminator appended after the formatted text
+    newline: Option<String>,
+}
 
-            __value_as_trait.#value_write_fn(__writer, #hint_pretty, #hint_radix, #hint_width, #hint_precision, #hint_case)
+impl Parse for FormatOptions {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let mut options = FormatOptions {
+            escape: EscapeMode::Percent,
+            dispatch: DispatchMode::Dynamic,
+            newline: None,
+        };
+        for attr in input.call(Attribute::parse_outer)? {
+            if !attr.path().is_ident("kformat") {
+                return Err(syn::Error::new_spanned(
+                    attr.path(),
+                    "Expected a #[kformat(...)] attribute",
+                ));
+            }
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("escape") {
+                    let value: Ident = meta.value()?.parse()?;
+                    options.escape = match value.to_string().as_str() {
+                        "percent" => EscapeMode::Percent,
+                        "braces" => EscapeMode::Braces,
+                        _ => {
+                            return Err(syn::Error::new_spanned(
+                                value,
+                                "Expected `percent` or `braces`",
+                            ))
+                        }
+                    };
+                    Ok(())
+                } else if meta.path.is_ident("dispatch") {
+                    let value: Ident = meta.value()?.call(Ident::parse_any)?;
+                    options.dispatch = match value.to_string().as_str() {
+                        "dynamic" => DispatchMode::Dynamic,
+                        "static" => DispatchMode::Static,
+                        _ => {
+                            return Err(syn::Error::new_spanned(
+                                value,
+                                "Expected `dynamic` or `static`",
+                            ))
+                        }
+                    };
+                    Ok(())
+                } else if meta.path.is_ident("newline") {
+                    // `newline` alone terminates the line with `\n`
+                    options.newline = Some(if meta.input.peek(Token![=]) {
+                        meta.value()?.parse::<LitStr>()?.value()
+                    } else {
+                        "\n".to_string()
+                    });
+                    Ok(())
+                } else {
+                    Err(meta.error("Unknown kformat option"))
+                }
+            })?;
         }
-    };
+        Ok(options)
+    }
+}
 
-    TokenStream::from(expanded)
+struct FormatArg {
+    name: Option<(Ident, Token![=])>,
+    value: Expr,
 }
 
-struct WriteInput {
-    writer: Ident,
-    _comma: Token![,],
-    trait_format: Ident,
-    _comma2: Token![,],
-    format_fn: Ident,
-    _comma3: Token![,],
-    trait_debug: Ident,
-    _comma4: Token![,],
-    debug_fn: Ident,
-    _comma5: Token![,],
-    trait_writeable: Ident,
-    _comma6: Token![,],
-    error_type: Ident,
-    _comma7: Token![,],
-    format: LitStr,
-    _comma8: Token![,],
-    args: Punctuated<Expr, Token![,]>,
+impl Parse for FormatArg {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let name = if input.peek(Ident) && input.peek2(Token![=]) && !input.peek2(Token![==]) {
+            Some((input.parse()?, input.parse()?))
+        } else {
+            None
+        };
+        Ok(Self {
+            name,
+            value: input.parse()?,
+        })
+    }
 }
 
 impl Parse for WriteInput {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
-        Ok(Self {
+        let mut write_input = Self {
+            options: input.parse()?,
             writer: input.parse()?,
             _comma: input.parse()?,
             trait_format: input.parse()?,
@@ -115,10 +153,17 @@ impl Parse for WriteInput {
             _comma6: input.parse()?,
             error_type: input.parse()?,
             _comma7: input.parse()?,
-            format: input.parse()?,
+            spec_type: input.parse()?,
             _comma8: input.parse()?,
-            args: Punctuated::parse_terminated(input)?,
-        })
+            format: input.parse()?,
+            _comma9: input.parse()?,
+            args: Punctuated::new(),
+        };
+        // Without a comma after the format string there are no arguments
+        if write_input._comma9.is_some() {
+            write_input.args = Punctuated::parse_terminated(input)?;
+        }
+        Ok(write_input)
     }
 }
 
@@ -128,51 +173,342 @@ enum FormatMethod {
     Debug,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 enum FormatHint {
     Pretty,
-    Radix(usize),
-    Width(usize),
-    Precision(usize),
+    Radix(FormatCount),
+    Width(FormatCount),
+    Precision(FormatCount),
     Lowercase,
     Uppercase,
+    Fill(char),
+    Align(FormatAlign),
+    Exponent,
+    Ellipsis,
+    SignPlus,
+    SignSpace,
+    ZeroPad,
+    Grouping(char),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FormatAlign {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum FormatArgRef {
+    Next,
+    Index(usize),
+    Name(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum FormatCount {
+    Literal(usize),
+    Arg(FormatArgRef),
 }
 
 #[derive(Debug, Clone)]
 struct FormatType {
+    // Byte range of the placeholder, braces included, in the format string
+    range: Range<usize>,
+    arg: FormatArgRef,
     method: FormatMethod,
     hints: Vec<FormatHint>,
 }
 
+// Error in a format string, `range` is the byte range of the offending text in the string value
+struct FormatStringError {
+    range: Range<usize>,
+    message: String,
+}
+
+impl FormatStringError {
+    fn new(range: Range<usize>, message: impl Into<String>) -> Self {
+        Self {
+            range,
+            message: message.into(),
+        }
+    }
+
+    // Points at the offending text when the compiler supports sub-spans (nightly only for now),
+    // and at the whole literal otherwise
+    fn to_compile_error(&self, format: &LitStr) -> TokenStream {
+        match fmt_subspan(format, &self.range) {
+            Some(span) => syn::Error::new(span, &self.message),
+            None => syn::Error::new_spanned(format, &self.message),
+        }
+        .to_compile_error()
+        .into()
+    }
+}
+
+fn fmt_subspan(format: &LitStr, range: &Range<usize>) -> Option<Span> {
+    let offsets = fmt_source_offsets(format)?;
+    format
+        .token()
+        .subspan(offsets[range.start]..offsets[range.end])
+}
+
+// Maps every byte offset in the value of `format` (end included) to the matching byte offset in
+// the literal's source, accounting for quotes, raw string delimiters and escape sequences
+fn fmt_source_offsets(format: &LitStr) -> Option<Vec<usize>> {
+    let source = format.token().to_string();
+    let value = format.value();
+
+    if let Some(raw) = source.strip_prefix('r') {
+        let start = raw.find('"')? + 2;
+        return Some((start..=start + value.len()).collect());
+    }
+
+    let content = source.strip_prefix('"')?.strip_suffix('"')?;
+    let mut offsets = Vec::with_capacity(value.len() + 1);
+    let mut chars = content.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let decoded = if c == '\\' {
+            match chars.next()?.1 {
+                'n' => Some('\n'),
+                'r' => Some('\r'),
+                't' => Some('\t'),
+                '0' => Some('\0'),
+                c @ ('\\' | '\'' | '"') => Some(c),
+                'x' => {
+                    let high = chars.next()?.1.to_digit(16)?;
+                    let low = chars.next()?.1.to_digit(16)?;
+                    char::from_u32(high * 16 + low)
+                }
+                'u' => {
+                    chars.next();
+                    let mut code = 0;
+                    for (_, c) in chars.by_ref() {
+                        match c {
+                            '}' => break,
+                            '_' => {}
+                            _ => code = code * 16 + c.to_digit(16)?,
+                        }
+                    }
+                    char::from_u32(code)
+                }
+                '\n' => {
+                    // Line continuation, skips the leading whitespace of the next line
+                    while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+                    None
+                }
+                _ => return None,
+            }
+        } else {
+            Some(c)
+        };
+        if let Some(decoded) = decoded {
+            offsets.extend((0..decoded.len_utf8()).map(|_| i + 1));
+        }
+    }
+    offsets.push(content.len() + 1);
+
+    if offsets.len() == value.len() + 1 {
+        Some(offsets)
+    } else {
+        None
+    }
+}
+
 enum FormatPart {
     Text(String),
     Placeholder(FormatType),
 }
 
-fn parse_fmt_placeholder(fmt: &str) -> Result<FormatType, TokenStream> {
+// In the first slot of a placeholder, an identifier is an argument name, captured from the
+// caller's scope when no argument has that name. Only the original hints stay hints there: the
+// radix and case letters, and `r`, `w` or `p` with their digits. The hints added since, which
+// could shadow a local (`e` would be the exponent hint, `g` the grouping hint), need a leading
+// `:`, as in `{:e}` or `{:g_}`. Hints that are not identifiers, such as `g'`, are read as hints.
+fn is_leading_hint(part: &str) -> bool {
+    match part {
+        "x" | "X" | "o" | "O" | "b" | "B" | "u" | "U" | "l" | "L" => true,
+        _ => {
+            // A bare `r`, `w` or `p` is a name, `{:w}` reports the missing value
+            let mut chars = part.chars();
+            matches!(chars.next(), Some('r' | 'R' | 'w' | 'W' | 'p' | 'P'))
+                && !chars.as_str().is_empty()
+                && chars.all(|c| c.is_ascii_digit())
+        }
+    }
+}
+
+// A decimal index or an identifier. Keywords and names that are not valid identifiers cannot be
+// captured, and are reported rather than left to fail in the generated code.
+fn parse_fmt_arg_name(
+    range: &Range<usize>,
+    part: &str,
+) -> Result<Option<FormatArgRef>, FormatStringError> {
+    if !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()) {
+        return part
+            .parse()
+            .map(|i| Some(FormatArgRef::Index(i)))
+            .map_err(|_| {
+                FormatStringError::new(
+                    range.clone(),
+                    format!("Argument index {} is too large", part),
+                )
+            });
+    }
+    // The parser skips surrounding whitespace, `{ x}` is not the argument `x`
+    if part.trim() != part {
+        return Ok(None);
+    }
+    if syn::parse_str::<Ident>(part).is_ok() {
+        return Ok(Some(FormatArgRef::Name(part.to_string())));
+    }
+    if syn::parse::Parser::parse_str(Ident::parse_any, part).is_ok() {
+        return Err(FormatStringError::new(
+            range.clone(),
+            format!(
+                "`{}` is a keyword and cannot be used as an argument name",
+                part
+            ),
+        ));
+    }
+    // Looks like a name, as opposed to a hint such as `w=name`
+    let is_word = part.chars().all(|c| c.is_alphanumeric() || c == '_');
+    if is_word && matches!(part.chars().next(), Some(c) if !c.is_ascii_digit()) {
+        return Err(FormatStringError::new(
+            range.clone(),
+            format!("Invalid argument name: {}", part),
+        ));
+    }
+    Ok(None)
+}
+
+// The first part of a placeholder refers to an argument if it is a decimal index, or an
+// identifier that is not one of the leading hints (`{x}` stays the lowercase hexadecimal hint).
+fn parse_fmt_arg_ref(
+    range: &Range<usize>,
+    part: &str,
+) -> Result<Option<FormatArgRef>, FormatStringError> {
+    if is_leading_hint(part) {
+        Ok(None)
+    } else {
+        parse_fmt_arg_name(range, part)
+    }
+}
+
+// Parses the value of a radix, width or precision hint: a number, `*` to take the next argument,
+// or `=` followed by an argument index or name.
+fn parse_fmt_count(
+    range: &Range<usize>,
+    part: &str,
+    name: &str,
+) -> Result<FormatCount, FormatStringError> {
+    let value = &part[1..];
+    if value == "*" {
+        Ok(FormatCount::Arg(FormatArgRef::Next))
+    } else if let Some(arg) = value.strip_prefix('=') {
+        match parse_fmt_arg_name(range, arg)? {
+            Some(arg) => Ok(FormatCount::Arg(arg)),
+            None => Err(FormatStringError::new(
+                range.clone(),
+                format!("Invalid argument reference in format hint: {}", part),
+            )),
+        }
+    } else {
+        parse_fmt_number(range, part, name).map(FormatCount::Literal)
+    }
+}
+
+// Parses the digits following the letter of a radix, width or precision hint
+fn parse_fmt_number(
+    range: &Range<usize>,
+    part: &str,
+    name: &str,
+) -> Result<usize, FormatStringError> {
+    let digits = &part[1..];
+    if digits.is_empty() {
+        return Err(FormatStringError::new(
+            range.clone(),
+            format!("Missing {} value in format hint: {}", name, part),
+        ));
+    }
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(FormatStringError::new(
+            range.clone(),
+            format!("Invalid {} value in format hint: {}", name, part),
+        ));
+    }
+    digits.parse().map_err(|_| {
+        FormatStringError::new(range.clone(), format!("Format hint {} is too large", part))
+    })
+}
+
+// `range` is the position of the placeholder, braces included, in the whole format string.
+// `offsets` maps every byte of `fmt` (end included) to its position in the whole format string,
+// which differs from `range.start + 1 + i` after a `%` escape.
+fn parse_fmt_placeholder(
+    fmt: &str,
+    offsets: &[usize],
+    range: Range<usize>,
+) -> Result<FormatType, FormatStringError> {
     if fmt.is_empty() {
         Ok(FormatType {
+            range,
+            arg: FormatArgRef::Next,
             method: FormatMethod::Display,
             hints: Vec::new(),
         })
     } else {
         let mut method = FormatMethod::Display;
-        let parts = fmt.split(':');
+        let mut start = 0;
+        let mut parts = fmt
+            .split(':')
+            .map(|part| {
+                let range = offsets[start]..offsets[start + part.len()];
+                start += part.len() + 1;
+                (range, part)
+            })
+            .peekable();
+        let first = match parts.peek() {
+            // `{:fd}` leaves the argument slot empty, like `core::fmt`
+            Some((_, "")) => {
+                parts.next();
+                None
+            }
+            Some((range, first)) => parse_fmt_arg_ref(range, first)?,
+            None => None,
+        };
+        let arg = match first {
+            Some(arg) => {
+                parts.next();
+                arg
+            }
+            None => FormatArgRef::Next,
+        };
         let mut hints = Vec::new();
-        for part in parts {
+        for (range, part) in parts {
             match part {
+                "" => {
+                    return Err(FormatStringError::new(
+                        range,
+                        format!("Empty format hint in placeholder {{{}}}", fmt),
+                    ));
+                }
                 "#" => {
-                    if method != FormatMethod::Display {
-                        return Err(syn::Error::new_spanned(
-                            fmt,
+                    if hints.contains(&FormatHint::Pretty) {
+                        return Err(FormatStringError::new(
+                            range,
                             "Duplicate '#' (pretty print) in format string",
-                        )
-                        .to_compile_error()
-                        .into());
+                        ));
                     }
                     hints.push(FormatHint::Pretty);
                 }
                 "?" => {
+                    if method == FormatMethod::Debug {
+                        return Err(FormatStringError::new(
+                            range,
+                            "Duplicate '?' (debug) in format string",
+                        ));
+                    }
                     method = FormatMethod::Debug;
                 }
                 "x" => {
@@ -180,22 +516,18 @@ fn parse_fmt_placeholder(fmt: &str) -> Result<FormatType, TokenStream> {
                         .iter()
                         .any(|hint| matches!(hint, FormatHint::Radix(_)))
                     {
-                        return Err(syn::Error::new_spanned(
-                            fmt,
+                        return Err(FormatStringError::new(
+                            range,
                             "Using 'x' (lowercase hexadecimal) hint when radix hint is already given",
-                        )
-                        .to_compile_error()
-                        .into());
+                        ));
                     }
                     if hints.iter().any(|hint| hint == &FormatHint::Uppercase) {
-                        return Err(syn::Error::new_spanned(
-                            fmt,
+                        return Err(FormatStringError::new(
+                            range,
                             "Using 'x' (lowercase hexadecimal) hint when uppercase hint is already given",
-                        )
-                        .to_compile_error()
-                        .into());
+                        ));
                     }
-                    hints.push(FormatHint::Radix(16));
+                    hints.push(FormatHint::Radix(FormatCount::Literal(16)));
                     if !hints.iter().any(|hint| hint == &FormatHint::Lowercase) {
                         hints.push(FormatHint::Lowercase);
                     }
@@ -205,22 +537,18 @@ fn parse_fmt_placeholder(fmt: &str) -> Result<FormatType, TokenStream> {
                         .iter()
                         .any(|hint| matches!(hint, FormatHint::Radix(_)))
                     {
-                        return Err(syn::Error::new_spanned(
-                            fmt,
+                        return Err(FormatStringError::new(
+                            range,
                             "Using 'X' (uppercase hexadecimal) hint when radix hint is already given",
-                        )
-                        .to_compile_error()
-                        .into());
+                        ));
                     }
                     if hints.iter().any(|hint| hint == &FormatHint::Lowercase) {
-                        return Err(syn::Error::new_spanned(
-                            fmt,
+                        return Err(FormatStringError::new(
+                            range,
                             "Using 'X' (uppercase hexadecimal) hint when lowercase hint is already given",
-                        )
-                        .to_compile_error()
-                        .into());
+                        ));
                     }
-                    hints.push(FormatHint::Radix(16));
+                    hints.push(FormatHint::Radix(FormatCount::Literal(16)));
                     if !hints.iter().any(|hint| hint == &FormatHint::Uppercase) {
                         hints.push(FormatHint::Uppercase);
                     }
@@ -230,149 +558,317 @@ fn parse_fmt_placeholder(fmt: &str) -> Result<FormatType, TokenStream> {
                         .iter()
                         .any(|hint| matches!(hint, FormatHint::Radix(_)))
                     {
-                        return Err(syn::Error::new_spanned(
-                            fmt,
+                        return Err(FormatStringError::new(
+                            range,
                             "Using 'o' or 'O' (octal) hint when radix hint is already given",
-                        )
-                        .to_compile_error()
-                        .into());
+                        ));
+                    }
+                    hints.push(FormatHint::Radix(FormatCount::Literal(8)));
+                }
+                "u" | "U" => {
+                    if hints.iter().any(|hint| hint == &FormatHint::Uppercase) {
+                        return Err(FormatStringError::new(
+                            range,
+                            "Using 'u' or 'U' (uppercase) hint when uppercase hint is already given",
+                        ));
+                    }
+                    hints.push(FormatHint::Uppercase);
+                }
+                "l" | "L" => {
+                    if hints.iter().any(|hint| hint == &FormatHint::Lowercase) {
+                        return Err(FormatStringError::new(
+                            range,
+                            "Using 'l' or 'L' (lowercase) hint when lowercase hint is already given",
+                        ));
+                    }
+                    hints.push(FormatHint::Lowercase);
+                }
+                "e" | "E" => {
+                    if hints.iter().any(|hint| hint == &FormatHint::Exponent) {
+                        return Err(FormatStringError::new(
+                            range,
+                            "Using 'e' or 'E' (exponent) hint when exponent hint is already given",
+                        ));
+                    }
+                    let (case, other_case) = if part == "e" {
+                        (FormatHint::Lowercase, FormatHint::Uppercase)
+                    } else {
+                        (FormatHint::Uppercase, FormatHint::Lowercase)
+                    };
+                    if hints.contains(&other_case) {
+                        return Err(FormatStringError::new(
+                            range,
+                            format!(
+                                "Using '{}' (exponent) hint when {} hint is already given",
+                                part,
+                                if part == "e" {
+                                    "uppercase"
+                                } else {
+                                    "lowercase"
+                                }
+                            ),
+                        ));
+                    }
+                    hints.push(FormatHint::Exponent);
+                    if !hints.contains(&case) {
+                        hints.push(case);
+                    }
+                }
+                "..." => {
+                    if hints.iter().any(|hint| hint == &FormatHint::Ellipsis) {
+                        return Err(FormatStringError::new(
+                            range,
+                            "Using '...' (ellipsis) hint when ellipsis hint is already given",
+                        ));
+                    }
+                    hints.push(FormatHint::Ellipsis);
+                }
+                "+" | " " => {
+                    if hints
+                        .iter()
+                        .any(|hint| matches!(hint, FormatHint::SignPlus | FormatHint::SignSpace))
+                    {
+                        return Err(FormatStringError::new(
+                            range,
+                            "Using '+' or ' ' (sign) hint when sign hint is already given",
+                        ));
                     }
-                    hints.push(FormatHint::Radix(8));
+                    hints.push(if part == "+" {
+                        FormatHint::SignPlus
+                    } else {
+                        FormatHint::SignSpace
+                    });
+                }
+                "<" | "^" | ">" => {
+                    if hints
+                        .iter()
+                        .any(|hint| matches!(hint, FormatHint::Align(_)))
+                    {
+                        return Err(FormatStringError::new(
+                            range,
+                            "Using '<', '^' or '>' (alignment) hint when alignment hint is already given",
+                        ));
+                    }
+                    hints.push(FormatHint::Align(match part {
+                        "<" => FormatAlign::Left,
+                        "^" => FormatAlign::Center,
+                        _ => FormatAlign::Right,
+                    }));
                 }
                 "b" | "B" => {
                     if hints
                         .iter()
                         .any(|hint| matches!(hint, FormatHint::Radix(_)))
                     {
-                        return Err(syn::Error::new_spanned(
-                            fmt,
+                        return Err(FormatStringError::new(
+                            range,
                             "Using 'b' or 'B' (binary) hint when radix hint is already given",
-                        )
-                        .to_compile_error()
-                        .into());
+                        ));
                     }
-                    hints.push(FormatHint::Radix(2));
+                    hints.push(FormatHint::Radix(FormatCount::Literal(2)));
                 }
                 _ => {
                     let chars = part.chars().collect::<Vec<char>>();
                     match chars[0] {
                         'r' | 'R' => {
+                            // The value is checked first, so that `{x:rX}` reports the invalid
+                            // value rather than a second radix. A radix taken from an argument is
+                            // checked by the integer impls.
+                            let radix = parse_fmt_count(&range, part, "radix")?;
+                            if let FormatCount::Literal(radix) = radix {
+                                if !(2..=36).contains(&radix) {
+                                    return Err(FormatStringError::new(
+                                        range,
+                                        format!("Radix must be between 2 and 36, found {}", radix),
+                                    ));
+                                }
+                            }
                             if hints
                                 .iter()
                                 .any(|hint| matches!(hint, FormatHint::Radix(_)))
                             {
-                                return Err(syn::Error::new_spanned(
-                                    fmt,
+                                return Err(FormatStringError::new(
+                                    range,
                                     "Using 'r' or 'R' (radix) hint when radix hint is already given",
-                                )
-                                .to_compile_error()
-                                .into());
+                                ));
                             }
-                            hints.push(FormatHint::Radix(
-                                chars[1..].iter().collect::<String>().parse().unwrap(),
-                            ));
+                            hints.push(FormatHint::Radix(radix));
+                        }
+                        'f' | 'F' => {
+                            if hints.iter().any(|hint| matches!(hint, FormatHint::Fill(_))) {
+                                return Err(FormatStringError::new(
+                                    range,
+                                    "Using 'f' or 'F' (fill) hint when fill hint is already given",
+                                ));
+                            }
+                            if chars.len() != 2 {
+                                return Err(FormatStringError::new(
+                                    range,
+                                    "The 'f' or 'F' (fill) hint must be followed by exactly one character",
+                                ));
+                            }
+                            hints.push(FormatHint::Fill(chars[1]));
                         }
                         'w' | 'W' => {
+                            let width = parse_fmt_count(&range, part, "width")?;
                             if hints
                                 .iter()
                                 .any(|hint| matches!(hint, FormatHint::Width(_)))
                             {
-                                return Err(syn::Error::new_spanned(
-                                    fmt,
+                                return Err(FormatStringError::new(
+                                    range,
                                     "Using 'w' or 'W' (width) hint when width hint is already given",
-                                )
-                                .to_compile_error()
-                                .into());
+                                ));
                             }
-                            hints.push(FormatHint::Width(
-                                chars[1..].iter().collect::<String>().parse().unwrap(),
-                            ));
+                            hints.push(FormatHint::Width(width));
                         }
                         'p' | 'P' => {
+                            let precision = parse_fmt_count(&range, part, "precision")?;
                             if hints
                                 .iter()
                                 .any(|hint| matches!(hint, FormatHint::Precision(_)))
                             {
-                                return Err(syn::Error::new_spanned(
-                                    fmt,
+                                return Err(FormatStringError::new(
+                                    range,
                                     "Using 'p' or 'P' (precision) hint when precision hint is already given",
-                                )
-                                .to_compile_error()
-                                .into());
+                                ));
                             }
-                            hints.push(FormatHint::Precision(
-                                chars[1..].iter().collect::<String>().parse().unwrap(),
-                            ));
+                            hints.push(FormatHint::Precision(precision));
                         }
-                        'u' | 'U' => {
-                            if hints.iter().any(|hint| hint == &FormatHint::Uppercase) {
-                                return Err(syn::Error::new_spanned(
-                                    fmt,
-                                    "Using 'u' or 'U' (uppercase) hint when uppercase hint is already given",
-                                )
-                                .to_compile_error()
-                                .into());
+                        'g' | 'G' => {
+                            if hints
+                                .iter()
+                                .any(|hint| matches!(hint, FormatHint::Grouping(_)))
+                            {
+                                return Err(FormatStringError::new(
+                                    range,
+                                    "Using 'g' or 'G' (grouping) hint when grouping hint is already given",
+                                ));
                             }
-                            hints.push(FormatHint::Uppercase);
+                            let separator = match chars.len() {
+                                1 => ',',
+                                2 if !chars[1].is_alphanumeric() => chars[1],
+                                _ => {
+                                    return Err(FormatStringError::new(
+                                        range,
+                                        "The 'g' or 'G' (grouping) hint can only be followed by one separator, neither a letter nor a digit",
+                                    ));
+                                }
+                            };
+                            hints.push(FormatHint::Grouping(separator));
                         }
-                        'l' | 'L' => {
-                            if hints.iter().any(|hint| hint == &FormatHint::Lowercase) {
-                                return Err(syn::Error::new_spanned(
-                                    fmt,
-                                    "Using 'l' or 'L' (lowercase) hint when lowercase hint is already given",
-                                )
-                                .to_compile_error()
-                                .into());
+                        '0' => {
+                            if hints.contains(&FormatHint::ZeroPad) {
+                                return Err(FormatStringError::new(
+                                    range,
+                                    "Using '0' (zero padding) hint when zero padding hint is already given",
+                                ));
+                            }
+                            hints.push(FormatHint::ZeroPad);
+                            // `0w8` is a shorthand for `0:w8`
+                            let width = &part[1..];
+                            if !width.is_empty() {
+                                if !width.starts_with(['w', 'W']) {
+                                    return Err(FormatStringError::new(
+                                        range,
+                                        "The '0' (zero padding) hint can only be followed by a width hint",
+                                    ));
+                                }
+                                if hints
+                                    .iter()
+                                    .any(|hint| matches!(hint, FormatHint::Width(_)))
+                                {
+                                    return Err(FormatStringError::new(
+                                        range,
+                                        "Using 'w' or 'W' (width) hint when width hint is already given",
+                                    ));
+                                }
+                                hints.push(FormatHint::Width(parse_fmt_count(
+                                    &range, width, "width",
+                                )?));
                             }
-                            hints.push(FormatHint::Lowercase);
                         }
                         _ => {
-                            return Err(syn::Error::new_spanned(
-                                fmt,
+                            return Err(FormatStringError::new(
+                                range,
                                 format!("Unknown format hint: {}", part),
-                            )
-                            .to_compile_error()
-                            .into());
+                            ));
                         }
                     }
                 }
             }
         }
 
-        Ok(FormatType { method, hints })
+        Ok(FormatType {
+            range,
+            arg,
+            method,
+            hints,
+        })
     }
 }
 
-fn parse_fmt_string(fmt: &str) -> Result<Vec<FormatPart>, TokenStream> {
+fn parse_fmt_string(fmt: &str, escape: EscapeMode) -> Result<Vec<FormatPart>, FormatStringError> {
     let mut parts = Vec::new();
 
     let mut part = String::new();
+    // Position in `fmt` of every byte of `part`
+    let mut part_offsets = Vec::new();
+    let mut part_start = 0;
     let mut in_placeholder = false;
-    let mut escape = false;
-    for c in fmt.chars() {
-        if escape {
-            escape = false;
-            part.push(c);
-            continue;
-        } else if c == '{' && !in_placeholder {
+    let mut chars = fmt.char_indices().peekable();
+    fn push(part: &mut String, part_offsets: &mut Vec<usize>, i: usize, c: char) {
+        part.push(c);
+        part_offsets.extend(i..i + c.len_utf8());
+    }
+    while let Some((i, c)) = chars.next() {
+        if escape == EscapeMode::Percent && c == '%' {
+            if let Some((i, c)) = chars.next() {
+                push(&mut part, &mut part_offsets, i, c);
+            }
+        } else if escape == EscapeMode::Braces
+            && !in_placeholder
+            && (c == '{' || c == '}')
+            && chars.next_if(|(_, next)| *next == c).is_some()
+        {
+            push(&mut part, &mut part_offsets, i, c);
+        } else if c == '{' && in_placeholder {
+            return Err(FormatStringError::new(
+                i..i + 1,
+                "Nested '{' in placeholder, placeholders cannot contain braces",
+            ));
+        } else if c == '{' {
             in_placeholder = true;
+            part_start = i + 1;
             if !part.is_empty() {
                 parts.push(FormatPart::Text(part));
                 part = String::new();
             }
-            continue;
+            part_offsets.clear();
         } else if c == '}' && in_placeholder {
             in_placeholder = false;
-            parts.push(FormatPart::Placeholder(parse_fmt_placeholder(&part)?));
+            part_offsets.push(i);
+            parts.push(FormatPart::Placeholder(parse_fmt_placeholder(
+                &part,
+                &part_offsets,
+                part_start - 1..i + 1,
+            )?));
             part = String::new();
-            continue;
-        } else if c == '%' {
-            escape = true;
-            continue;
+        } else if c == '}' && escape == EscapeMode::Braces {
+            return Err(FormatStringError::new(
+                i..i + 1,
+                "Unmatched '}' in format string, use '}}' to write a literal '}'",
+            ));
         } else {
-            part.push(c);
+            push(&mut part, &mut part_offsets, i, c);
         }
     }
+    if in_placeholder {
+        return Err(FormatStringError::new(
+            part_start - 1..fmt.len(),
+            "Unclosed placeholder, expected '}'",
+        ));
+    }
     if !part.is_empty() {
         parts.push(FormatPart::Text(part));
     }
@@ -380,9 +876,209 @@ fn parse_fmt_string(fmt: &str) -> Result<Vec<FormatPart>, TokenStream> {
     Ok(parts)
 }
 
+fn fmt_str_literal(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Str(lit), ..
+        }) => Some(lit.value()),
+        Expr::Group(group) => fmt_str_literal(&group.expr),
+        Expr::Paren(paren) => fmt_str_literal(&paren.expr),
+        _ => None,
+    }
+}
+
+// Indices of the arguments used by one placeholder
+struct PlaceholderArgs {
+    value: usize,
+    radix: Option<usize>,
+    width: Option<usize>,
+    precision: Option<usize>,
+}
+
+struct ArgResolver<'a> {
+    format: &'a LitStr,
+    args: &'a mut Vec<FormatArg>,
+    explicit_args: usize,
+    expected_args: usize,
+    names: HashMap<String, usize>,
+    used: Vec<bool>,
+    count_next: usize,
+}
+
+impl ArgResolver<'_> {
+    fn resolve(
+        &mut self,
+        placeholder: &FormatType,
+        arg: &FormatArgRef,
+    ) -> Result<usize, TokenStream> {
+        let index = match arg {
+            FormatArgRef::Next => {
+                if self.count_next >= self.explicit_args {
+                    return Err(FormatStringError::new(
+                        placeholder.range.clone(),
+                        format!(
+                            "Expected {} arguments for format string, but found {}",
+                            self.expected_args, self.explicit_args
+                        ),
+                    )
+                    .to_compile_error(self.format));
+                }
+                self.count_next += 1;
+                self.count_next - 1
+            }
+            FormatArgRef::Index(index) => {
+                if *index >= self.explicit_args {
+                    return Err(FormatStringError::new(
+                        placeholder.range.clone(),
+                        format!(
+                            "Invalid reference to positional argument {} ({} arguments were given)",
+                            index, self.explicit_args
+                        ),
+                    )
+                    .to_compile_error(self.format));
+                }
+                *index
+            }
+            FormatArgRef::Name(name) => match self.names.get(name) {
+                Some(index) => *index,
+                None => {
+                    // The identifier takes the span of the format literal so that it resolves
+                    // at the caller's site, even when the literal went through `macro_rules!`
+                    let span = fmt_subspan(self.format, &placeholder.range)
+                        .unwrap_or_else(|| self.format.span());
+                    let mut ident: Ident =
+                        syn::parse_str(name).expect("argument names are checked when parsed");
+                    ident.set_span(span);
+                    self.names.insert(name.clone(), self.args.len());
+                    self.used.push(true);
+                    self.args.push(FormatArg {
+                        name: Some((ident.clone(), Token![=](span))),
+                        value: Expr::Path(syn::ExprPath {
+                            attrs: Vec::new(),
+                            qself: None,
+                            path: ident.into(),
+                        }),
+                    });
+                    self.args.len() - 1
+                }
+            },
+        };
+        self.used[index] = true;
+        Ok(index)
+    }
+}
+
+// Maps every placeholder to the indices of the arguments it uses, and checks that every
+// argument is referenced at least once. Names that do not match a named argument are captured
+// from the surrounding scope and appended to `args`. Radix, width and precision arguments are
+// taken in the order their hints are written, before the value itself.
+fn resolve_fmt_args(
+    format: &LitStr,
+    placeholders: &[&FormatType],
+    args: &mut Vec<FormatArg>,
+) -> Result<Vec<PlaceholderArgs>, TokenStream> {
+    let explicit_args = args.len();
+    let mut names = HashMap::new();
+    for (index, arg) in args.iter().enumerate() {
+        match &arg.name {
+            Some((name, _)) => {
+                if names.insert(name.to_string(), index).is_some() {
+                    return Err(syn::Error::new_spanned(
+                        name,
+                        format!("Duplicate argument named `{}`", name),
+                    )
+                    .to_compile_error()
+                    .into());
+                }
+            }
+            None => {
+                if !names.is_empty() {
+                    return Err(syn::Error::new_spanned(
+                        &arg.value,
+                        "Positional arguments cannot follow named arguments",
+                    )
+                    .to_compile_error()
+                    .into());
+                }
+            }
+        }
+    }
+
+    let expected_args = placeholders
+        .iter()
+        .map(|p| {
+            let counts = p
+                .hints
+                .iter()
+                .filter(|hint| match hint {
+                    FormatHint::Radix(count)
+                    | FormatHint::Width(count)
+                    | FormatHint::Precision(count) => {
+                        count == &FormatCount::Arg(FormatArgRef::Next)
+                    }
+                    _ => false,
+                })
+                .count();
+            counts + usize::from(p.arg == FormatArgRef::Next)
+        })
+        .sum::<usize>();
+    let mut resolver = ArgResolver {
+        format,
+        args,
+        explicit_args,
+        expected_args,
+        names,
+        used: vec![false; explicit_args],
+        count_next: 0,
+    };
+    let mut resolved = Vec::with_capacity(placeholders.len());
+    for placeholder in placeholders {
+        let mut radix = None;
+        let mut width = None;
+        let mut precision = None;
+        for hint in placeholder.hints.iter() {
+            match hint {
+                FormatHint::Radix(FormatCount::Arg(arg)) => {
+                    radix = Some(resolver.resolve(placeholder, arg)?);
+                }
+                FormatHint::Width(FormatCount::Arg(arg)) => {
+                    width = Some(resolver.resolve(placeholder, arg)?);
+                }
+                FormatHint::Precision(FormatCount::Arg(arg)) => {
+                    precision = Some(resolver.resolve(placeholder, arg)?);
+                }
+                _ => {}
+            }
+        }
+        resolved.push(PlaceholderArgs {
+            value: resolver.resolve(placeholder, &placeholder.arg)?,
+            radix,
+            width,
+            precision,
+        });
+    }
+
+    if let Some(unused) = resolver.used.iter().position(|used| !used) {
+        return Err(syn::Error::new_spanned(
+            &resolver.args[unused].value,
+            "Argument never used in format string",
+        )
+        .to_compile_error()
+        .into());
+    }
+
+    Ok(resolved)
+}
+
 #[proc_macro]
 pub fn kwrite_to_raw(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as WriteInput);
+    expand_kwrite(input)
+}
+
+fn expand_kwrite(input: WriteInput) -> TokenStream {
     let WriteInput {
+        options,
         writer,
         format,
         args,
@@ -392,116 +1088,884 @@ pub fn kwrite_to_raw(input: TokenStream) -> TokenStream {
         format_fn,
         debug_fn,
         error_type,
+        spec_type,
         ..
-    } = parse_macro_input!(input as WriteInput);
+    } = input;
 
-    let args = args.iter().cloned().collect::<Vec<_>>();
+    let mut args = args.into_iter().collect::<Vec<_>>();
 
     // Extract the format string as a plain string
     let format_str = format.value();
 
-    let parsed_fmt = match parse_fmt_string(&format_str) {
+    let mut parsed_fmt = match parse_fmt_string(&format_str, options.escape) {
         Ok(fmt) => fmt,
         Err(e) => {
-            return e;
+            return e.to_compile_error(&format);
         }
     };
+    if let Some(newline) = options.newline {
+        parsed_fmt.push(FormatPart::Text(newline));
+    }
 
     let placeholders = parsed_fmt
         .iter()
         .filter_map(|part| match part {
-            FormatPart::Placeholder(p) => Some(p.clone()),
+            FormatPart::Placeholder(p) => Some(p),
             _ => None,
         })
         .collect::<Vec<_>>();
 
-    if placeholders.len() != args.len() {
-        return syn::Error::new_spanned(
-            format,
-            format!(
-                "Expected {} arguments for format string, but found {}",
-                placeholders.len(),
-                args.len()
-            ),
-        )
-        .to_compile_error()
-        .into();
-    }
+    let placeholder_args = match resolve_fmt_args(&format, &placeholders, &mut args) {
+        Ok(indices) => indices,
+        Err(e) => {
+            return e;
+        }
+    };
+
+    // Every argument is evaluated exactly once, then bound by reference
+    let arg_values = args.iter().map(|arg| &arg.value).collect::<Vec<_>>();
+    let arg_idents = (0..args.len())
+        .map(|i| format_ident!("__arg{}", i))
+        .collect::<Vec<_>>();
 
     let mut combined = proc_macro2::TokenStream::new();
     let mut count_placeholders = 0;
 
+    // `value` is a reference to the value to write
+    let write_call = |value: proc_macro2::TokenStream,
+                      value_trait: &Path,
+                      value_write_fn: &Ident,
+                      spec: proc_macro2::TokenStream| match options.dispatch {
+        DispatchMode::Dynamic => quote! {
+            __result += {
+                let __value_as_trait: &dyn #value_trait = #value;
+                __value_as_trait.#value_write_fn(&mut *__writer, #spec)
+            }?;
+        },
+        DispatchMode::Static => quote! {
+            match #value_trait::#value_write_fn(#value, &mut *__writer, #spec) {
+                ::core::result::Result::Ok(__count) => __result += __count,
+                ::core::result::Result::Err(__error) => {
+                    break '__kformat ::core::result::Result::Err(::core::convert::From::from(__error));
+                }
+            }
+        },
+    };
+
+    // Literal text is accumulated here, so that adjacent text is written with a single call
+    let mut text = String::new();
+    let write_text = |text: &mut String| {
+        if text.is_empty() {
+            return proc_macro2::TokenStream::new();
+        }
+        let value = std::mem::take(text);
+        let spec = quote! { &#spec_type { ..::core::default::Default::default() } };
+        write_call(quote! { &#value }, &trait_format, &format_fn, spec)
+    };
+
     for part in parsed_fmt.iter() {
         let expanded = match part {
             FormatPart::Text(t) => {
-                let value = t.clone();
-                quote! {
-                    __result += (write_single!(#value, #trait_format, #format_fn, *__writer, #trait_writeable, core::option::Option::None, core::option::Option::None, core::option::Option::None, core::option::Option::None, core::option::Option::None))?;
-                }
+                text.push_str(t);
+                continue;
             }
             FormatPart::Placeholder(p) => {
-                let mut hint_pretty = quote! { core::option::Option::None };
-                let mut hint_radix = quote! { core::option::Option::None };
-                let mut hint_width = quote! { core::option::Option::None };
-                let mut hint_precision = quote! { core::option::Option::None };
-                let mut hint_case = quote! { core::option::Option::None };
+                // A string literal displayed without hints is written exactly like literal text
+                let value = &args[placeholder_args[count_placeholders].value].value;
+                if let (FormatMethod::Display, true, Some(literal)) =
+                    (p.method, p.hints.is_empty(), fmt_str_literal(value))
+                {
+                    text.push_str(&literal);
+                    count_placeholders += 1;
+                    continue;
+                }
+                combined.extend(write_text(&mut text));
+
+                // Only the fields given by hints are set, the others keep their default value
+                let mut fields = Vec::new();
                 for hint in p.hints.iter() {
-                    match hint {
+                    fields.push(match hint {
                         FormatHint::Lowercase => {
-                            hint_case = quote! { core::option::Option::Some(false) };
+                            quote! { case: ::core::option::Option::Some(false) }
                         }
                         FormatHint::Uppercase => {
-                            hint_case = quote! { core::option::Option::Some(true) };
+                            quote! { case: ::core::option::Option::Some(true) }
                         }
                         FormatHint::Pretty => {
-                            hint_pretty = quote! { core::option::Option::Some(true) };
+                            quote! { alternate: true }
                         }
-                        FormatHint::Radix(r) => {
+                        FormatHint::Radix(FormatCount::Literal(r)) => {
                             let radix = *r;
-                            hint_radix = quote! { core::option::Option::Some(#radix) };
+                            quote! { radix: ::core::option::Option::Some(#radix) }
                         }
-                        FormatHint::Width(w) => {
+                        FormatHint::Radix(FormatCount::Arg(_)) => {
+                            let radix =
+                                &arg_idents[placeholder_args[count_placeholders].radix.unwrap()];
+                            quote! { radix: ::core::option::Option::Some(*#radix) }
+                        }
+                        FormatHint::Width(FormatCount::Literal(w)) => {
                             let width = *w;
-                            hint_width = quote! { core::option::Option::Some(#width) };
+                            quote! { width: ::core::option::Option::Some(#width) }
+                        }
+                        FormatHint::Width(FormatCount::Arg(_)) => {
+                            let width =
+                                &arg_idents[placeholder_args[count_placeholders].width.unwrap()];
+                            quote! { width: ::core::option::Option::Some(*#width) }
                         }
-                        FormatHint::Precision(p) => {
+                        FormatHint::Precision(FormatCount::Literal(p)) => {
                             let prec = *p;
-                            hint_precision = quote! { core::option::Option::Some(#prec) };
+                            quote! { precision: ::core::option::Option::Some(#prec) }
                         }
-                    }
+                        FormatHint::Precision(FormatCount::Arg(_)) => {
+                            let prec = &arg_idents
+                                [placeholder_args[count_placeholders].precision.unwrap()];
+                            quote! { precision: ::core::option::Option::Some(*#prec) }
+                        }
+                        FormatHint::Fill(f) => {
+                            let fill = *f;
+                            quote! { fill: #fill }
+                        }
+                        FormatHint::Align(a) => {
+                            let align = match a {
+                                FormatAlign::Left => quote! { ::core::fmt::Alignment::Left },
+                                FormatAlign::Center => quote! { ::core::fmt::Alignment::Center },
+                                FormatAlign::Right => quote! { ::core::fmt::Alignment::Right },
+                            };
+                            quote! { align: ::core::option::Option::Some(#align) }
+                        }
+                        FormatHint::Exponent => {
+                            quote! { exponent: true }
+                        }
+                        FormatHint::Ellipsis => {
+                            quote! { ellipsis: true }
+                        }
+                        FormatHint::SignPlus => {
+                            quote! { sign_plus: true }
+                        }
+                        FormatHint::SignSpace => {
+                            quote! { sign_space: true }
+                        }
+                        FormatHint::ZeroPad => {
+                            quote! { zero_pad: true }
+                        }
+                        FormatHint::Grouping(g) => {
+                            let separator = *g;
+                            quote! { grouping: ::core::option::Option::Some(#separator) }
+                        }
+                    });
                 }
+                let spec = quote! {
+                    &#spec_type { #(#fields,)* ..::core::default::Default::default() }
+                };
+                let value = &arg_idents[placeholder_args[count_placeholders].value];
+                count_placeholders += 1;
                 match p.method {
                     FormatMethod::Display => {
-                        let value = args.get(count_placeholders).unwrap();
-                        count_placeholders += 1;
-                        quote! {
-                            __result += (write_single!((#value), #trait_format, #format_fn, *__writer, #trait_writeable, #hint_pretty, #hint_radix, #hint_width, #hint_precision, #hint_case))?;
-                        }
+                        write_call(quote! { #value }, &trait_format, &format_fn, spec)
                     }
                     FormatMethod::Debug => {
-                        let value = args.get(count_placeholders).unwrap();
-                        count_placeholders += 1;
-                        quote! {
-                            __result += (write_single!((#value), #trait_debug, #debug_fn, *__writer, #trait_writeable, #hint_pretty, #hint_radix, #hint_width, #hint_precision, #hint_case))?;
-                        }
+                        write_call(quote! { #value }, &trait_debug, &debug_fn, spec)
                     }
                 }
             }
         };
         combined.extend(expanded);
     }
+    combined.extend(write_text(&mut text));
 
-    let result = quote! {
-        {
-            let __closure: &dyn core::ops::Fn(&mut dyn #trait_writeable) -> core::result::Result<usize, #error_type> = &(|__writer| {
-                use kformat_macros::write_single;
+    let body = match options.dispatch {
+        DispatchMode::Dynamic => quote! {
+            let __closure: &dyn ::core::ops::Fn(&mut dyn #trait_writeable) -> ::core::result::Result<usize, #error_type> = &(|__writer| {
                 let mut __result: usize = 0;
                 #combined
-                Ok(__result)
+                ::core::result::Result::Ok(__result)
             });
-            __closure(&mut #writer)
+            __closure(&mut (#writer))
+        },
+        DispatchMode::Static => quote! {
+            let __writer = &mut (#writer);
+            #[allow(unused_labels, unused_mut)]
+            let __result: ::core::result::Result<usize, #error_type> = '__kformat: {
+                let mut __result: usize = 0;
+                #combined
+                ::core::result::Result::Ok(__result)
+            };
+            __result
+        },
+    };
+
+    let result = quote! {
+        match (#(&(#arg_values),)*) {
+            (#(#arg_idents,)*) => {
+                #body
+            }
         }
     };
 
     TokenStream::from(result)
 }
+
+// Input of the macros generated by `define_kformat!`:
+// `[traits and types] [ln? options] #[kformat(...)]* writer, "format", args...`
+fn parse_facade_input(input: syn::parse::ParseStream) -> syn::Result<WriteInput> {
+    let config;
+    syn::bracketed!(config in input);
+    let config: proc_macro2::TokenStream = config.parse()?;
+    let defaults;
+    syn::bracketed!(defaults in input);
+    // `ln` marks the `…ln!` macros, the only ones taking a writer without a format string
+    let line = defaults.peek(Ident);
+    if line {
+        let flag: Ident = defaults.parse()?;
+        if flag != "ln" {
+            return Err(syn::Error::new_spanned(flag, "Unknown facade flag"));
+        }
+    }
+    let defaults: proc_macro2::TokenStream = defaults.parse()?;
+
+    let options = input.call(Attribute::parse_outer)?;
+    let writer: Expr = input.parse()?;
+    let mut rest = proc_macro2::TokenStream::new();
+    if !(line && input.is_empty()) {
+        input.parse::<Token![,]>()?;
+        rest = input.parse()?;
+    }
+    if line && rest.is_empty() {
+        // Only the line terminator is written
+        rest = quote! { "" };
+    }
+
+    // The options of the invocation come last, so they override the defaults of the facade
+    syn::parse2(quote! { #defaults #(#options)* #writer, #config, #rest })
+}
+
+#[doc(hidden)]
+#[proc_macro]
+pub fn kwrite_facade(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input with parse_facade_input);
+    expand_kwrite(input)
+}
+
+struct FacadeDefinition {
+    format_trait: Path,
+    format_fn: Ident,
+    debug_trait: Path,
+    debug_fn: Ident,
+    writer: Path,
+    error: Type,
+    spec: Path,
+    macro_name: Ident,
+    format_macro: Ident,
+    newline: LitStr,
+    krate: Path,
+}
+
+fn facade_key<T>(value: Option<T>, key: &str) -> syn::Result<T> {
+    value.ok_or_else(|| syn::Error::new(Span::call_site(), format!("Missing `{}`", key)))
+}
+
+impl Parse for FacadeDefinition {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let mut format_trait = None;
+        let mut format_fn = None;
+        let mut debug_trait = None;
+        let mut debug_fn = None;
+        let mut writer = None;
+        let mut error = None;
+        let mut spec = None;
+        let mut macro_name = None;
+        let mut format_macro = None;
+        let mut newline = None;
+        let mut krate = None;
+
+        let mut keys = HashSet::new();
+        while !input.is_empty() {
+            let key = input.call(Ident::parse_any)?;
+            if !keys.insert(key.to_string()) {
+                return Err(syn::Error::new_spanned(key, "Duplicate key"));
+            }
+            input.parse::<Token![=]>()?;
+            match key.to_string().as_str() {
+                "format_trait" => format_trait = Some(input.parse()?),
+                "format_fn" => format_fn = Some(input.parse()?),
+                "debug_trait" => debug_trait = Some(input.parse()?),
+                "debug_fn" => debug_fn = Some(input.parse()?),
+                "writer" => writer = Some(input.parse()?),
+                "error" => error = Some(input.parse()?),
+                "spec" => spec = Some(input.parse()?),
+                "macro_name" => macro_name = Some(input.parse()?),
+                "format_macro" => format_macro = Some(input.parse()?),
+                "newline" => newline = Some(input.parse()?),
+                "crate" => krate = Some(input.parse()?),
+                _ => return Err(syn::Error::new_spanned(key, "Unknown define_kformat key")),
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+
+        let format_trait: Path = facade_key(format_trait, "format_trait")?;
+        let format_fn: Ident = facade_key(format_fn, "format_fn")?;
+        Ok(Self {
+            // Debug placeholders use the format trait unless a debug trait is given
+            debug_trait: debug_trait.unwrap_or_else(|| format_trait.clone()),
+            debug_fn: debug_fn.unwrap_or_else(|| format_fn.clone()),
+            format_trait,
+            format_fn,
+            writer: facade_key(writer, "writer")?,
+            error: facade_key(error, "error")?,
+            spec: facade_key(spec, "spec")?,
+            macro_name: macro_name.unwrap_or_else(|| Ident::new("kwrite", Span::call_site())),
+            format_macro: format_macro.unwrap_or_else(|| Ident::new("kformat", Span::call_site())),
+            newline: newline.unwrap_or_else(|| LitStr::new("\n", Span::call_site())),
+            krate: krate.unwrap_or_else(|| syn::parse_quote!(::kformat_macros)),
+        })
+    }
+}
+
+// Paths starting with `crate::` are resolved from the crate defining the facade
+fn facade_path(path: impl ToTokens) -> proc_macro2::TokenStream {
+    let mut tokens = path.into_token_stream().into_iter().peekable();
+    match tokens.peek() {
+        Some(TokenTree::Ident(ident)) if ident == "crate" => {
+            tokens.next();
+            quote! { $crate #(#tokens)* }
+        }
+        _ => tokens.collect(),
+    }
+}
+
+#[proc_macro]
+pub fn define_kformat(input: TokenStream) -> TokenStream {
+    let FacadeDefinition {
+        format_trait,
+        format_fn,
+        debug_trait,
+        debug_fn,
+        writer,
+        error,
+        spec,
+        macro_name,
+        format_macro,
+        newline,
+        krate,
+    } = parse_macro_input!(input as FacadeDefinition);
+
+    let format_trait = facade_path(format_trait);
+    let debug_trait = facade_path(debug_trait);
+    let writer = facade_path(writer);
+    let error = facade_path(error);
+    let spec = facade_path(spec);
+    let krate = facade_path(krate);
+    let config = quote! {
+        #format_trait, #format_fn, #debug_trait, #debug_fn, #writer, #error, #spec
+    };
+    let macro_name_ln = format_ident!("{}ln", macro_name);
+    let ln_doc = format!(
+        "Same as `{}!`, followed by {:?}. Without a format string, only the line terminator is written.",
+        macro_name,
+        newline.value()
+    );
+
+    // Each macro is exported under a hidden name and imported under its own, so it can be
+    // referred to by path, `crate::kwrite!` or `use crate::kwrite`, in the defining crate too
+    let hidden = |name: &Ident| format_ident!("__kformat_{}", name);
+    let (hidden_name, hidden_name_ln, hidden_format) = (
+        hidden(&macro_name),
+        hidden(&macro_name_ln),
+        hidden(&format_macro),
+    );
+
+    let expanded = quote! {
+        #[doc(hidden)]
+        #[macro_export]
+        macro_rules! #hidden_name {
+            ($($input:tt)*) => {
+                #krate::kwrite_facade!([#config] [] $($input)*)
+            };
+        }
+
+        #[doc(hidden)]
+        #[macro_export]
+        macro_rules! #hidden_name_ln {
+            ($($input:tt)*) => {
+                #krate::kwrite_facade!([#config] [ln #[kformat(newline = #newline)]] $($input)*)
+            };
+        }
+
+        #[doc(hidden)]
+        #[macro_export]
+        macro_rules! #hidden_format {
+            ($(#[$option:meta])* $fmt:literal $($args:tt)*) => {
+                |__kformat_writer: &mut dyn #writer| {
+                    #krate::kwrite_facade!([#config] [] $(#[$option])* *__kformat_writer, $fmt $($args)*)
+                }
+            };
+        }
+
+        /// Writes formatted text to a writer, see `kwrite_to_raw!` for the format string.
+        #[doc(inline)]
+        #[allow(unused_imports)]
+        pub use #hidden_name as #macro_name;
+
+        #[doc = #ln_doc]
+        #[doc(inline)]
+        #[allow(unused_imports)]
+        pub use #hidden_name_ln as #macro_name_ln;
+
+        /// Returns a closure writing the formatted text to the writer it is given.
+        /// The arguments are borrowed, and evaluated each time the closure is called.
+        #[doc(inline)]
+        #[allow(unused_imports)]
+        pub use #hidden_format as #format_macro;
+    };
+
+    TokenStream::from(expanded)
+}
+
+// Options of `#[kformat(...)]` attributes on a derived type, its variants and fields
+#[derive(Default)]
+struct DeriveOptions {
+    skip: bool,
+    hex: bool,
+    rename: Option<LitStr>,
+    with: Option<Path>,
+    krate: Option<Path>,
+}
+
+fn parse_derive_options(attrs: &[Attribute], allowed: &[&str]) -> syn::Result<DeriveOptions> {
+    let mut options = DeriveOptions::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("kformat")) {
+        attr.parse_nested_meta(|meta| {
+            let key = meta.path.get_ident().map(|ident| ident.to_string());
+            match key.as_deref() {
+                Some(key) if !allowed.contains(&key) => {
+                    return Err(meta.error(format!("`{}` is not supported here", key)));
+                }
+                Some("skip") => options.skip = true,
+                Some("hex") => options.hex = true,
+                Some("rename") => options.rename = Some(meta.value()?.parse()?),
+                Some("with") => options.with = Some(meta.value()?.parse()?),
+                Some("crate") => options.krate = Some(meta.value()?.parse()?),
+                _ => return Err(meta.error("Unknown kformat option")),
+            }
+            Ok(())
+        })?;
+    }
+    Ok(options)
+}
+
+// Match arm writing `name` and `fields` with the debug builders of kformat_default_nostd
+fn derive_arm(
+    krate: &Path,
+    field_trait: &proc_macro2::TokenStream,
+    pattern_path: proc_macro2::TokenStream,
+    name: String,
+    fields: &syn::Fields,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let mut patterns = Vec::new();
+    let mut calls = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let options = parse_derive_options(&field.attrs, &["skip", "hex", "rename", "with"])?;
+        let binding = format_ident!("__field{}", i);
+        patterns.push(match &field.ident {
+            Some(ident) if options.skip => quote! { #ident: _ },
+            Some(ident) => quote! { #ident: #binding },
+            None if options.skip => quote! { _ },
+            None => quote! { #binding },
+        });
+        if options.skip {
+            continue;
+        }
+
+        let spec = if options.hex {
+            quote! {
+                &#krate::FormatSpec {
+                    radix: ::core::option::Option::Some(16),
+                    case: ::core::option::Option::Some(false),
+                    ..*__spec
+                }
+            }
+        } else {
+            quote! { __spec }
+        };
+        let value = match &options.with {
+            Some(with) => quote! { &|__writer, __spec| #with(#binding, __writer, #spec) },
+            None => quote! { &|__writer, __spec| #field_trait(#binding, __writer, #spec) },
+        };
+        let label = match (&options.rename, &field.ident) {
+            (Some(rename), _) => Some(rename.value()),
+            (None, Some(ident)) => Some(ident.unraw().to_string()),
+            (None, None) => None,
+        };
+        calls.push(match label {
+            Some(label) => quote! { .field_with(#label, #value) },
+            None => quote! { .field_with(#value) },
+        });
+    }
+
+    Ok(match fields {
+        syn::Fields::Named(_) => quote! {
+            #pattern_path { #(#patterns),* } => {
+                #krate::debug_struct(__writer, __spec, #name)#(#calls)*.finish()
+            }
+        },
+        syn::Fields::Unnamed(_) => quote! {
+            #pattern_path(#(#patterns),*) => {
+                #krate::debug_tuple(__writer, __spec, #name)#(#calls)*.finish()
+            }
+        },
+        syn::Fields::Unit => quote! {
+            #pattern_path => #krate::debug_struct(__writer, __spec, #name).finish(),
+        },
+    })
+}
+
+fn derive_kformat(
+    input: TokenStream,
+    trait_name: &str,
+    write_fn: &str,
+    field_fn: &str,
+) -> TokenStream {
+    let input = parse_macro_input!(input as syn::DeriveInput);
+    match derive_kformat_impl(input, trait_name, write_fn, field_fn) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn derive_kformat_impl(
+    input: syn::DeriveInput,
+    trait_name: &str,
+    write_fn: &str,
+    field_fn: &str,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let options = parse_derive_options(&input.attrs, &["crate", "rename"])?;
+    let krate = options
+        .krate
+        .unwrap_or_else(|| syn::parse_quote!(::kformat_default_nostd));
+    let trait_ident = Ident::new(trait_name, Span::call_site());
+    let trait_path = quote! { #krate::#trait_ident };
+    let write_fn = Ident::new(write_fn, Span::call_site());
+    let field_fn = Ident::new(field_fn, Span::call_site());
+    let field_trait = quote! { #trait_path::#field_fn };
+
+    let ident = &input.ident;
+    let name = match &options.rename {
+        Some(rename) => rename.value(),
+        None => ident.unraw().to_string(),
+    };
+    let arms = match &input.data {
+        syn::Data::Struct(data) => {
+            vec![derive_arm(
+                &krate,
+                &field_trait,
+                quote! { Self },
+                name,
+                &data.fields,
+            )?]
+        }
+        syn::Data::Enum(data) => {
+            let mut arms = Vec::new();
+            for variant in data.variants.iter() {
+                let options = parse_derive_options(&variant.attrs, &["rename"])?;
+                let variant_ident = &variant.ident;
+                let name = match &options.rename {
+                    Some(rename) => rename.value(),
+                    None => variant_ident.unraw().to_string(),
+                };
+                arms.push(derive_arm(
+                    &krate,
+                    &field_trait,
+                    quote! { Self::#variant_ident },
+                    name,
+                    &variant.fields,
+                )?);
+            }
+            arms
+        }
+        syn::Data::Union(data) => {
+            return Err(syn::Error::new_spanned(
+                data.union_token,
+                format!("{} cannot be derived for unions", trait_name),
+            ));
+        }
+    };
+
+    let body = if arms.is_empty() {
+        // Without variants there is no value to write
+        quote! { match *self {} }
+    } else {
+        quote! { match self { #(#arms)* } }
+    };
+
+    // Every type parameter must implement the derived trait, like the std derives
+    let mut generics = input.generics.clone();
+    let type_params = generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .collect::<Vec<_>>();
+    let where_clause = generics.make_where_clause();
+    for param in type_params {
+        where_clause
+            .predicates
+            .push(syn::parse_quote!(#param: #trait_path));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics #trait_path for #ident #ty_generics #where_clause {
+            fn #write_fn(
+                &self,
+                __writer: &mut dyn #krate::Writeable,
+                __spec: &#krate::FormatSpec,
+            ) -> ::core::result::Result<usize, #krate::FormatError> {
+                #body
+            }
+        }
+    })
+}
+
+/// Implements `Formattable` with the output of `#[derive(Debug)]`, the fields being written
+/// with their `Formattable` implementation.
+///
+/// Fields accept `#[kformat(skip)]`, `#[kformat(hex)]`, `#[kformat(rename = "...")]` and
+/// `#[kformat(with = path)]`, where `path` is a function with the signature of `write_format`
+/// taking the field by reference. Types and variants accept `#[kformat(rename = "...")]`, and
+/// `#[kformat(crate = path)]` gives the path of kformat_default_nostd.
+#[proc_macro_derive(Formattable, attributes(kformat))]
+pub fn derive_formattable(input: TokenStream) -> TokenStream {
+    derive_kformat(input, "Formattable", "write_format", "write_format_aligned")
+}
+
+/// Implements `Debuggable` like `#[derive(Debug)]`, see `#[derive(Formattable)]` for the options.
+#[proc_macro_derive(Debuggable, attributes(kformat))]
+pub fn derive_debuggable(input: TokenStream) -> TokenStream {
+    derive_kformat(input, "Debuggable", "write_debug", "write_debug_aligned")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The error message, and the part of the format string it points at
+    fn parse_error(fmt: &str, escape: EscapeMode) -> Option<(String, String)> {
+        parse_fmt_string(fmt, escape)
+            .err()
+            .map(|error| (error.message, fmt[error.range].to_string()))
+    }
+
+    fn parse_args(fmt: &str) -> Vec<FormatArgRef> {
+        match parse_fmt_string(fmt, EscapeMode::Braces) {
+            Ok(parts) => parts
+                .into_iter()
+                .filter_map(|part| match part {
+                    FormatPart::Placeholder(p) => Some(p.arg),
+                    FormatPart::Text(_) => None,
+                })
+                .collect(),
+            Err(error) => panic!("{}", error.message),
+        }
+    }
+
+    #[test]
+    fn test_parse_arg_names() {
+        assert_eq!(
+            parse_args("{name} {r#type} {_x} {é}"),
+            [
+                FormatArgRef::Name("name".to_string()),
+                FormatArgRef::Name("r#type".to_string()),
+                FormatArgRef::Name("_x".to_string()),
+                FormatArgRef::Name("é".to_string()),
+            ]
+        );
+
+        // Only the original hints are read as hints in the first slot
+        assert_eq!(
+            parse_args("{e} {E:p2} {:e} {x} {w4} {fd} {:fd} {f.} {g} {g_} {:g_} {g'} {p} {w:x}"),
+            [
+                FormatArgRef::Name("e".to_string()),
+                FormatArgRef::Name("E".to_string()),
+                FormatArgRef::Next,
+                FormatArgRef::Next,
+                FormatArgRef::Next,
+                FormatArgRef::Name("fd".to_string()),
+                FormatArgRef::Next,
+                FormatArgRef::Next,
+                FormatArgRef::Name("g".to_string()),
+                FormatArgRef::Name("g_".to_string()),
+                FormatArgRef::Next,
+                FormatArgRef::Next,
+                FormatArgRef::Name("p".to_string()),
+                FormatArgRef::Name("w".to_string()),
+            ]
+        );
+
+        let (message, at) = parse_error("a {while} b", EscapeMode::Braces).unwrap();
+        assert_eq!(
+            message,
+            "`while` is a keyword and cannot be used as an argument name"
+        );
+        assert_eq!(at, "while");
+        let (message, at) = parse_error("{\u{345}}", EscapeMode::Braces).unwrap();
+        assert_eq!(message, "Invalid argument name: \u{345}");
+        assert_eq!(at, "\u{345}");
+        let (message, _) = parse_error("{w=fn}", EscapeMode::Braces).unwrap();
+        assert_eq!(
+            message,
+            "`fn` is a keyword and cannot be used as an argument name"
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_placeholders() {
+        let cases = [
+            ("{:w}", "Missing width value in format hint: w", "w"),
+            ("{ x}", "Unknown format hint:  x", " x"),
+            ("{x }", "Unknown format hint: x ", "x "),
+            ("{:p}", "Missing precision value in format hint: p", "p"),
+            ("{r1}", "Radix must be between 2 and 36, found 1", "r1"),
+            ("{r40}", "Radix must be between 2 and 36, found 40", "r40"),
+            ("{x:rX}", "Invalid radix value in format hint: rX", "rX"),
+            (
+                "{x:r8}",
+                "Using 'r' or 'R' (radix) hint when radix hint is already given",
+                "r8",
+            ),
+            ("{a::b}", "Empty format hint in placeholder {a::b}", ""),
+            (
+                "{w99999999999999999999}",
+                "Format hint w99999999999999999999 is too large",
+                "w99999999999999999999",
+            ),
+            (
+                "{99999999999999999999}",
+                "Argument index 99999999999999999999 is too large",
+                "99999999999999999999",
+            ),
+            (
+                "{:w=99999999999999999999}",
+                "Argument index 99999999999999999999 is too large",
+                "w=99999999999999999999",
+            ),
+            (
+                "{w=}",
+                "Invalid argument reference in format hint: w=",
+                "w=",
+            ),
+            ("{:q}", "Unknown format hint: q", "q"),
+            ("{?:?}", "Duplicate '?' (debug) in format string", "?"),
+            (
+                "{#:f}",
+                "The 'f' or 'F' (fill) hint must be followed by exactly one character",
+                "f",
+            ),
+        ];
+        for (fmt, message, at) in cases {
+            assert_eq!(
+                parse_error(fmt, EscapeMode::Braces),
+                Some((message.to_string(), at.to_string())),
+                "{}",
+                fmt
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_percent_escape_ranges() {
+        // Sub-ranges point at the source, not at the unescaped text
+        let error = parse_fmt_string("100%% {x:%w}", EscapeMode::Percent)
+            .err()
+            .unwrap();
+        assert_eq!(error.message, "Missing width value in format hint: w");
+        assert_eq!(error.range, 10..11);
+        let (message, at) = parse_error("%{é {#:%q:w2}", EscapeMode::Percent).unwrap();
+        assert_eq!(message, "Unknown format hint: q");
+        assert_eq!(at, "q");
+    }
+
+    #[test]
+    fn test_fmt_source_offsets() {
+        let offsets = |source: &str| fmt_source_offsets(&syn::parse_str::<LitStr>(source).unwrap());
+
+        assert_eq!(offsets(r#""a{}""#), Some(vec![1, 2, 3, 4]));
+        assert_eq!(offsets(r##"r#"a{}"#"##), Some(vec![3, 4, 5, 6]));
+        assert_eq!(offsets(r#"r"{}""#), Some(vec![2, 3, 4]));
+        // `é` is two bytes, both at the start of its escape
+        assert_eq!(offsets(r#""a\u{e9}b""#), Some(vec![1, 2, 2, 8, 9]));
+        assert_eq!(offsets(r#""\x41\n{""#), Some(vec![1, 5, 7, 8]));
+        // Line continuation
+        assert_eq!(offsets("\"a\\\n    {}\""), Some(vec![1, 8, 9, 10]));
+    }
+
+    #[test]
+    fn test_parse_braces_escape() {
+        let parts = parse_fmt_string("{{ {} }}}}", EscapeMode::Braces)
+            .ok()
+            .unwrap();
+        assert!(matches!(&parts[..], [
+            FormatPart::Text(open),
+            FormatPart::Placeholder(_),
+            FormatPart::Text(close),
+        ] if open == "{ " && close == " }}"));
+
+        let cases = [
+            ("a {w4", "Unclosed placeholder, expected '}'", "{w4"),
+            ("{{{", "Unclosed placeholder, expected '}'", "{"),
+            (
+                "{a{b}}",
+                "Nested '{' in placeholder, placeholders cannot contain braces",
+                "{",
+            ),
+            (
+                "a } b",
+                "Unmatched '}' in format string, use '}}' to write a literal '}'",
+                "}",
+            ),
+            (
+                "{}}",
+                "Unmatched '}' in format string, use '}}' to write a literal '}'",
+                "}",
+            ),
+        ];
+        for (fmt, message, at) in cases {
+            assert_eq!(
+                parse_error(fmt, EscapeMode::Braces),
+                Some((message.to_string(), at.to_string())),
+                "{}",
+                fmt
+            );
+        }
+
+        // A lone `}` is text with `%` escapes, the other errors are the same
+        assert!(parse_fmt_string("a } b", EscapeMode::Percent).is_ok());
+        assert_eq!(
+            parse_error("{a{b}}", EscapeMode::Percent).map(|(message, _)| message),
+            Some("Nested '{' in placeholder, placeholders cannot contain braces".to_string())
+        );
+        assert_eq!(
+            parse_error("a {", EscapeMode::Percent).map(|(message, _)| message),
+            Some("Unclosed placeholder, expected '}'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_facade_input() {
+        let parse = |input: proc_macro2::TokenStream| {
+            syn::parse::Parser::parse2(parse_facade_input, input).map(|_| ())
+        };
+        let config = quote! { [Fmt, fmt, Dbg, dbg, W, E, Spec] };
+        let line = quote! { #config [ln #[kformat(newline = "\n")]] };
+
+        assert!(parse(quote! { #config [] b, "x {}", 1 }).is_ok());
+        assert!(parse(quote! { #line b, "x {}", 1 }).is_ok());
+        // Only the `…ln!` macros take a writer alone
+        assert!(parse(quote! { #line b }).is_ok());
+        assert!(parse(quote! { #line b, }).is_ok());
+        assert!(parse(quote! { #config [] b }).is_err());
+        assert!(parse(quote! { #config [] b, }).is_err());
+        // The comma after the writer is required
+        assert!(parse(quote! { #config [] b "x {}", 1 }).is_err());
+        assert!(parse(quote! { #line b "x {}", 1 }).is_err());
+    }
+}